@@ -0,0 +1,59 @@
+//! Compares looking up every color against the palette with using a Quantizer,
+//! on a 1080p frame. Run with `cargo bench --bench color256`.
+
+extern crate image;
+extern crate termplay;
+
+use image::{Rgb, RgbImage};
+use std::time::{Duration, Instant};
+use termplay::converters::*;
+
+const RUNS: u32 = 5;
+
+fn frame() -> RgbImage {
+    RgbImage::from_fn(1920, 1080, |x, y| {
+        Rgb([(x * 255 / 1919) as u8, (y * 255 / 1079) as u8, ((x + y) % 256) as u8])
+    })
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let frame = frame();
+
+    for &metric in &[ColorMetric::Manhattan, ColorMetric::Oklab] {
+        let scan = time(|| {
+            for pixel in frame.pixels() {
+                if metric == ColorMetric::Manhattan {
+                    lookup_color(pixel[0], pixel[1], pixel[2]);
+                } else {
+                    lookup_color_with(metric, pixel[0], pixel[1], pixel[2]);
+                }
+            }
+        });
+
+        let quantizer = Quantizer::new(COLORS, metric);
+        let first = time(|| {
+            let quantizer = Quantizer::new(COLORS, metric);
+            for pixel in frame.pixels() {
+                quantizer.lookup(pixel[0], pixel[1], pixel[2]);
+            }
+        });
+        let table = time(|| {
+            for pixel in frame.pixels() {
+                quantizer.lookup(pixel[0], pixel[1], pixel[2]);
+            }
+        });
+
+        println!("{:?}:", metric);
+        println!("    palette scan:           {:>10.2?}/frame", scan);
+        println!("    quantizer, first frame: {:>10.2?}/frame", first);
+        println!("    quantizer, later frames: {:>9.2?}/frame", table);
+    }
+}
@@ -0,0 +1,58 @@
+//! Measures how long the text converters take for a frame on a large terminal,
+//! 320 columns by 90 rows. Run with `cargo bench --bench render`,
+//! and add `--features rayon` to render rows in parallel.
+//!
+//! Each converter is timed twice: through `display`, which copies the image first,
+//! and through `display_frame` on a `Frame` borrowing the RGB buffer, like video frames are.
+
+extern crate image;
+extern crate termplay;
+
+use image::{Rgb, RgbImage};
+use std::time::{Duration, Instant};
+use termplay::converters::*;
+
+const RUNS: u32 = 20;
+const COLUMNS: u32 = 320;
+const ROWS: u32 = 90;
+
+fn frame(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, ((x * y) % 256) as u8])
+    })
+}
+
+fn time<C: Converter>(converter: C) -> (Duration, Duration) {
+    let (width, height) = converter.actual_pos(COLUMNS, ROWS);
+    let frame = frame(width, height);
+    let mut buf = Vec::new();
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        buf.clear();
+        converter.display(&mut buf, &frame).unwrap();
+    }
+    let image = start.elapsed() / RUNS;
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        buf.clear();
+        converter.display_frame(&mut buf, &Frame::from_rgb(&frame)).unwrap();
+    }
+    (image, start.elapsed() / RUNS)
+}
+
+fn main() {
+    let results = [
+        ("truecolor", time(TrueColor::default())),
+        ("halfblock", time(HalfBlock::default())),
+        ("quadrant", time(Quadrant)),
+        ("sextant", time(Sextant))
+    ];
+    for &(name, (image, frame)) in &results {
+        println!(
+            "{:>9}: {:>8.2?} per image, {:>8.2?} per frame, {:>6.1} fps",
+            name, image, frame, 1.0 / frame.as_secs_f64()
+        );
+    }
+}
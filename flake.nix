@@ -29,7 +29,6 @@
       nativeBuildInputs = with pkgs; [ pkgconfig makeWrapper ];
       buildInputs = with pkgs; [
         glib
-        libsixel
 
         # gstreamer - Needed to compile
         gst_all_1.gstreamer
@@ -0,0 +1,168 @@
+//! Detect what the terminal supports, to pick the best converter
+
+use converters::*;
+
+#[cfg(feature = "termion")] use query;
+use std::{env, time::Duration};
+
+/// Ask kitty to validate a 1x1 image without storing it
+#[cfg(feature = "termion")]
+const KITTY_QUERY: &[u8] = b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+/// XTGETTCAP for the RGB and Tc capabilities, which mean truecolor support
+#[cfg(feature = "termion")]
+const XTGETTCAP_QUERY: &[u8] = b"\x1bP+q524742;5463\x1b\\";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// What a terminal is able to display
+pub struct Capabilities {
+    pub sixel: bool,
+    pub kitty: bool,
+    pub iterm2: bool,
+    pub truecolor: bool,
+    pub color256: bool,
+    /// Running inside tmux or screen, which don't pass images through
+    pub multiplexer: bool
+}
+impl Capabilities {
+    /// Guess capabilities from environment variables like TERM and COLORTERM
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+    /// Guess capabilities from environment variables, as returned by `var`
+    pub fn from_vars<F>(var: F) -> Self
+        where F: Fn(&str) -> Option<String>
+    {
+        let term = var("TERM").unwrap_or_default();
+        let program = var("TERM_PROGRAM").unwrap_or_default();
+        let colorterm = var("COLORTERM").unwrap_or_default();
+
+        let kitty = term.contains("kitty") || var("KITTY_WINDOW_ID").is_some() || program == "WezTerm";
+        let iterm2 = program == "iTerm.app" || program == "WezTerm" || program == "mintty"
+            || var("LC_TERMINAL").map(|t| t == "iTerm2").unwrap_or(false)
+            || var("KONSOLE_VERSION").is_some();
+        let sixel = term.contains("mlterm") || term.contains("foot") || term.contains("yaft");
+        let truecolor = colorterm == "truecolor" || colorterm == "24bit" || term.contains("direct")
+            || kitty || iterm2;
+        let color256 = truecolor || term.contains("256color");
+        let multiplexer = var("TMUX").is_some() || var("STY").is_some()
+            || term.starts_with("screen") || term.starts_with("tmux");
+
+        Self {
+            sixel,
+            kitty,
+            iterm2,
+            truecolor,
+            color256,
+            multiplexer
+        }
+    }
+    /// Guess capabilities from environment variables, and if possible ask the terminal directly.
+    /// Gives up on the terminal after `timeout`, which is only reached if it doesn't reply at all.
+    #[cfg(feature = "termion")]
+    pub fn detect(timeout: Duration) -> Self {
+        let mut capabilities = Self::from_env();
+
+        let mut request = Vec::new();
+        request.extend_from_slice(KITTY_QUERY);
+        request.extend_from_slice(XTGETTCAP_QUERY);
+
+        if let Ok(reply) = query::query(&request, timeout) {
+            capabilities.apply_reply(&reply);
+        }
+        capabilities
+    }
+    /// Guess capabilities from environment variables.
+    /// Without termion, there's no way to ask the terminal.
+    #[cfg(not(feature = "termion"))]
+    pub fn detect(_timeout: Duration) -> Self {
+        Self::from_env()
+    }
+    /// Update the capabilities from the terminal's replies to `detect`'s queries
+    #[cfg(feature = "termion")]
+    pub fn apply_reply(&mut self, reply: &[u8]) {
+        let contains = |needle: &[u8]| reply.windows(needle.len()).any(|window| window == needle);
+
+        if contains(b"\x1b_Gi=31;OK") {
+            self.kitty = true;
+            self.truecolor = true;
+        }
+        if contains(b"\x1bP1+r") {
+            self.truecolor = true;
+        }
+        if let Some(params) = query::da1(reply) {
+            if params.iter().skip(1).any(|&param| param == 4) {
+                self.sixel = true;
+            }
+        }
+        self.color256 |= self.truecolor;
+    }
+    /// Return the name of the best converter for these capabilities, as used by `Registry`
+    pub fn best_name(&self) -> &'static str {
+        if !self.multiplexer {
+            if self.kitty {
+                return "kitty";
+            }
+            if self.iterm2 {
+                return "iterm2";
+            }
+            if cfg!(feature = "sixel") && self.sixel {
+                return "sixel";
+            }
+        }
+        if self.truecolor {
+            "halfblock"
+        } else if self.color256 {
+            "color256"
+        } else {
+            "ansi16"
+        }
+    }
+    /// Return the best converter for these capabilities, with default settings
+    pub fn best_converter(&self) -> DynamicConverter {
+        match self.best_name() {
+            "kitty" => DynamicConverter::Kitty(Kitty::default()),
+            "iterm2" => DynamicConverter::ITerm2(ITerm2::default()),
+            #[cfg(feature = "sixel")] "sixel" => DynamicConverter::Sixel(Sixel::default()),
+            "halfblock" => DynamicConverter::HalfBlock(HalfBlock::default()),
+            "color256" => DynamicConverter::Color256(Color256::default()),
+            _ => DynamicConverter::Ansi16(Ansi16::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| vars.iter().find(|&&(key, _)| key == name).map(|&(_, value)| value.to_string())
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let caps = Capabilities::from_vars(vars(&[("TERM", "xterm-kitty")]));
+        assert!(caps.kitty && caps.truecolor && !caps.multiplexer);
+
+        let caps = Capabilities::from_vars(vars(&[("TERM", "xterm-256color"), ("TERM_PROGRAM", "iTerm.app")]));
+        assert!(caps.iterm2 && !caps.kitty);
+
+        let caps = Capabilities::from_vars(vars(&[("TERM", "screen-256color"), ("TMUX", "/tmp/tmux"), ("COLORTERM", "truecolor")]));
+        assert!(caps.multiplexer && caps.truecolor);
+        assert_eq!(caps.best_name(), "halfblock");
+        match caps.best_converter() {
+            DynamicConverter::HalfBlock(_) => (),
+            other => panic!("expected halfblock, got {:?}", other)
+        }
+
+        let caps = Capabilities::from_vars(vars(&[("TERM", "linux")]));
+        assert_eq!(caps, Capabilities::default());
+        assert_eq!(caps.best_name(), "ansi16");
+    }
+    #[cfg(feature = "termion")]
+    #[test]
+    fn test_apply_reply() {
+        let mut caps = Capabilities::default();
+        caps.apply_reply(b"\x1bP1+r524742=38\x1b\\\x1b[?62;4;22c");
+        assert!(caps.sixel && caps.truecolor && caps.color256 && !caps.kitty);
+    }
+}
@@ -0,0 +1,76 @@
+//! Handling of transparent images
+
+use image::{Pixel, Rgba, RgbaImage};
+
+/// Pixels less opaque than this are left to the terminal's background
+pub const ALPHA_THRESHOLD: u8 = 128;
+
+/// Return the color of a pixel, or None if it's transparent
+pub fn visible<P: Pixel<Subpixel = u8>>(pixel: P) -> Option<[u8; 3]> {
+    let pixel = pixel.to_rgba();
+    if pixel[3] < ALPHA_THRESHOLD {
+        None
+    } else {
+        Some([pixel[0], pixel[1], pixel[2]])
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// What to show behind transparent parts of an image
+pub enum Background {
+    /// Let the terminal's own background show through.
+    /// Character based converters leave transparent cells untouched,
+    /// and the kitty, iTerm2 and sixel converters send the transparency along.
+    #[default]
+    Terminal,
+    /// Blend the image onto a color
+    Color(u8, u8, u8),
+    /// Blend the image onto a gray checkerboard, like most image editors do
+    Checkerboard
+}
+impl Background {
+    /// Blend the image onto this background, which makes it fully opaque.
+    /// `tile` is the size of each checkerboard square.
+    /// Does nothing for `Background::Terminal`.
+    pub fn composite(self, image: &mut RgbaImage, tile: (u32, u32)) {
+        let (tile_width, tile_height) = (tile.0.max(1), tile.1.max(1));
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let background = match self {
+                Background::Terminal => return,
+                Background::Color(r, g, b) => [r, g, b],
+                Background::Checkerboard => if (x / tile_width + y / tile_height) % 2 == 0 {
+                    [153, 153, 153]
+                } else {
+                    [102, 102, 102]
+                }
+            };
+            let alpha = pixel[3] as u32;
+            let blend = |fg: u8, bg: u8| ((fg as u32 * alpha + bg as u32 * (255 - alpha) + 127) / 255) as u8;
+            *pixel = Rgba([
+                blend(pixel[0], background[0]),
+                blend(pixel[1], background[1]),
+                blend(pixel[2], background[2]),
+                255
+            ]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_composite() {
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([255, 0, 0, 0]));
+        image.put_pixel(1, 0, Rgba([255, 0, 0, 128]));
+
+        let mut terminal = image.clone();
+        Background::Terminal.composite(&mut terminal, (1, 1));
+        assert_eq!(terminal, image);
+
+        Background::Color(0, 0, 255).composite(&mut image, (1, 1));
+        assert_eq!(image.get_pixel(0, 0), &Rgba([0, 0, 255, 255]));
+        assert_eq!(image.get_pixel(1, 0), &Rgba([128, 0, 127, 255]));
+    }
+}
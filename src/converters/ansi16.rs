@@ -0,0 +1,152 @@
+use super::{
+    cells::CellBuffer,
+    frame::Frame,
+    color256::COLORS,
+    sgr::Color,
+    ColorMetric, Dither, Quantizer
+};
+
+use image::{GenericImage, Pixel};
+use std::{
+    io::{self, Write},
+    sync::Arc
+};
+
+#[derive(Clone, Debug)]
+pub struct Ansi16 {
+    /// What the 16 colors look like in the terminal.
+    /// Terminal themes often change these, so set this to match yours.
+    pub palette: [(u8, u8, u8); 16],
+    /// How to find the closest color in the palette
+    pub metric: ColorMetric,
+    pub dither: Dither,
+    /// A quantizer for `palette` and `metric`, see `with_palette`. When set, this is used instead.
+    /// Without it, palettes other than the xterm colors need a new table for every image.
+    pub quantizer: Option<Arc<Quantizer>>
+}
+impl Default for Ansi16 {
+    fn default() -> Self {
+        let mut palette = [(0, 0, 0); 16];
+        palette.copy_from_slice(&COLORS[..16]);
+        Self {
+            palette,
+            metric: ColorMetric::default(),
+            dither: Dither::default(),
+            quantizer: None
+        }
+    }
+}
+
+impl Ansi16 {
+    /// Create a converter for a terminal with a different palette,
+    /// and build the quantizer for it once
+    pub fn with_palette(palette: [(u8, u8, u8); 16], metric: ColorMetric) -> Self {
+        Self {
+            palette,
+            metric,
+            quantizer: Some(Arc::new(Quantizer::new(&palette, metric))),
+            ..Self::default()
+        }
+    }
+    /// Convert an image to a grid of cells, one for each pixel
+    pub fn cells<I, P>(&self, image: &I) -> CellBuffer
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.frame_cells(&Frame::new(image))
+    }
+    /// Like `cells`, but reads the raw pixels directly
+    pub fn frame_cells(&self, frame: &Frame) -> CellBuffer {
+        let width = frame.width() as usize;
+        let mut pixels = Vec::with_capacity(width * frame.height() as usize);
+        let mut opaque = Vec::with_capacity(pixels.capacity());
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                let pixel = frame.visible(x, y);
+                pixels.push(pixel.unwrap_or([0, 0, 0]));
+                opaque.push(pixel.is_some());
+            }
+        }
+
+        let owned;
+        let quantizer = match self.quantizer {
+            Some(ref quantizer) => quantizer,
+            None if self.palette[..] == COLORS[..16] => Quantizer::xterm16(self.metric),
+            None => {
+                owned = Quantizer::new(&self.palette, self.metric);
+                &owned
+            }
+        };
+        let indices = self.dither.apply(&pixels, width, quantizer.palette(), |r, g, b| quantizer.lookup(r, g, b));
+
+        let mut cells = CellBuffer::new(frame.width(), frame.height());
+        for (i, (&index, &opaque)) in indices.iter().zip(&opaque).enumerate() {
+            if opaque {
+                cells.get_mut((i % width) as u32, (i / width) as u32).bg = Color::Indexed(index);
+            }
+        }
+        cells
+    }
+}
+
+impl super::Converter for Ansi16 {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.cells(image).write_ansi(fmt, false)
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        Some(self.cells(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.frame_cells(frame).write_ansi(fmt, false)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        Some(self.frame_cells(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use converters::Converter;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_palette() {
+        let image = RgbImage::from_pixel(1, 1, Rgb([200, 0, 0]));
+        let bg = |ansi16: &Ansi16| ansi16.cells(&image).get(0, 0).bg;
+        assert_eq!(bg(&Ansi16::default()), Color::Indexed(9));
+
+        // A terminal theme whose only red is color 5
+        let mut palette = [(0, 0, 0); 16];
+        palette[5] = (200, 0, 0);
+        assert_eq!(bg(&Ansi16::with_palette(palette, ColorMetric::Manhattan)), Color::Indexed(5));
+        assert_eq!(bg(&Ansi16 { palette, ..Ansi16::default() }), Color::Indexed(5));
+    }
+    #[test]
+    fn test_sgr() {
+        let image = RgbImage::from_fn(32, 8, |x, y| Rgb([(x * 8) as u8, (y * 32) as u8, ((x * y) % 256) as u8]));
+        let output = String::from_utf8(Ansi16::default().to_vec(&image)).unwrap();
+
+        let mut params = 0;
+        for sgr in output.split("\x1b[").skip(1) {
+            let end = sgr.find('m').unwrap();
+            for param in sgr[..end].split(';') {
+                let param: u32 = param.parse().unwrap();
+                assert!(
+                    matches!(param, 0 | 30..=37 | 40..=47 | 90..=97 | 100..=107),
+                    "{} is not one of the 16 colors", param
+                );
+                params += 1;
+            }
+        }
+        assert!(params > 16);
+    }
+}
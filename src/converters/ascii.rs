@@ -0,0 +1,143 @@
+use super::{
+    cells::{Cell, CellBuffer},
+    frame::Frame,
+    sgr::Color
+};
+
+use image::{GenericImage, Pixel};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// How to tint the characters of an Ascii image
+pub enum AsciiColor {
+    /// Plain text, without any escape sequences
+    None,
+    /// The closest of the 16 standard terminal colors
+    Ansi16,
+    /// The exact RGB color
+    TrueColor
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ascii {
+    /// The characters to use, from darkest to brightest
+    pub ramp: &'static str,
+    pub color: AsciiColor,
+    /// Use the ramp backwards, for dark text on light backgrounds
+    pub invert: bool
+}
+impl Default for Ascii {
+    fn default() -> Self {
+        Self {
+            ramp: " .:-=+*#%@",
+            color: AsciiColor::None,
+            invert: false
+        }
+    }
+}
+
+impl Ascii {
+    /// Convert an image to a grid of cells, one for each pixel.
+    /// Fails if the ramp is empty.
+    pub fn cells<I, P>(&self, image: &I) -> io::Result<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.frame_cells(&Frame::new(image))
+    }
+    /// Like `cells`, but reads the raw pixels directly
+    pub fn frame_cells(&self, frame: &Frame) -> io::Result<CellBuffer> {
+        let ramp: Vec<char> = self.ramp.chars().collect();
+        if ramp.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "character ramp is empty"));
+        }
+
+        let mut cells = CellBuffer::new(frame.width(), frame.height());
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                let pixel = match frame.visible(x, y) {
+                    Some(pixel) => pixel,
+                    None => continue
+                };
+                let luma = (pixel[0] as usize * 299 + pixel[1] as usize * 587 + pixel[2] as usize * 114) / 1000;
+
+                let mut i = luma * ramp.len() / 256;
+                if self.invert {
+                    i = ramp.len() - 1 - i;
+                }
+
+                let fg = match self.color {
+                    AsciiColor::None => Color::Default,
+                    AsciiColor::Ansi16 => Color::Indexed(super::color256::lookup_color_16(pixel[0], pixel[1], pixel[2])),
+                    AsciiColor::TrueColor => Color::Rgb(pixel[0], pixel[1], pixel[2])
+                };
+                *cells.get_mut(x, y) = Cell { glyph: ramp[i], fg, ..Cell::default() };
+            }
+        }
+        Ok(cells)
+    }
+}
+
+impl super::Converter for Ascii {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.cells(image)?.write_ansi(fmt, false)
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.cells(image).ok()
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.frame_cells(frame)?.write_ansi(fmt, false)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        self.frame_cells(frame).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use converters::Converter;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_ramp() {
+        let image = RgbaImage::from_fn(4, 1, |x, _| match x {
+            0 => Rgba([0, 0, 0, 255]),
+            1 => Rgba([128, 128, 128, 255]),
+            2 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([255, 255, 255, 0])
+        });
+        let glyphs = |ascii: Ascii| -> String {
+            let cells = ascii.cells(&image).unwrap();
+            (0..4).map(|x| cells.get(x, 0).glyph).collect()
+        };
+        assert_eq!(glyphs(Ascii::default()), " +@ ");
+        // Transparent pixels stay blank, even when inverted
+        assert_eq!(glyphs(Ascii { invert: true, ..Ascii::default() }), "@=  ");
+        assert_eq!(glyphs(Ascii { ramp: "ab", ..Ascii::default() }), "abb ");
+
+        let cells = Ascii { color: AsciiColor::TrueColor, ..Ascii::default() }.cells(&image).unwrap();
+        assert_eq!(cells.get(1, 0).fg, Color::Rgb(128, 128, 128));
+        let cells = Ascii::default().cells(&image).unwrap();
+        assert!(!cells.has_style());
+
+        assert!(Ascii { ramp: "", ..Ascii::default() }.cells(&image).is_err());
+    }
+    #[test]
+    fn test_plain() {
+        // Plain text is meant for files and pagers, so lines end like they do there
+        let image = RgbaImage::from_fn(2, 2, |x, y| Rgba([(x * 255) as u8, (y * 128) as u8, 0, 255]));
+        assert_eq!(Ascii::default().to_vec(&image), b" :\n:+");
+
+        let colored = Ascii { color: AsciiColor::Ansi16, ..Ascii::default() }.to_vec(&image);
+        assert!(colored.starts_with(b"\x1b["));
+    }
+}
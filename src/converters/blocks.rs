@@ -0,0 +1,187 @@
+//! Shared code for converters that split each character into a grid of pixels
+
+use super::{
+    cells::{Cell, CellBuffer},
+    frame::{map_rows, Frame},
+    sgr::Color
+};
+
+use std::char;
+
+/// Quadrant glyphs, indexed by a mask of top left, top right, bottom left, bottom right
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛',
+    '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'
+];
+
+/// Return the quadrant glyph for a mask of the pixels, from the top left to the bottom right
+pub fn quadrant(mask: u32) -> char {
+    QUADRANTS[mask as usize]
+}
+/// Return the sextant glyph from Unicode 13 for a mask of the pixels,
+/// from the top left to the bottom right
+pub fn sextant(mask: u32) -> char {
+    match mask {
+        // These already existed before Unicode 13, and are not repeated
+        0 => ' ',
+        0b010101 => '▌',
+        0b101010 => '▐',
+        0b111111 => '█',
+        mask => {
+            let skipped = (mask > 0b010101) as u32 + (mask > 0b101010) as u32;
+            char::from_u32(0x1FB00 + mask - 1 - skipped).unwrap()
+        }
+    }
+}
+/// The reverse of `quadrant` and `sextant`: return how many columns and rows of pixels
+/// a block glyph is split into, and the mask of which are drawn in the foreground.
+/// Returns None for other glyphs.
+pub fn block_mask(glyph: char) -> Option<(u32, u32, u32)> {
+    if let Some(mask) = QUADRANTS.iter().position(|&quadrant| quadrant == glyph) {
+        return Some((2, 2, mask as u32));
+    }
+    match glyph as u32 {
+        code @ 0x1FB00..=0x1FB3B => {
+            let mut mask = code - 0x1FB00 + 1;
+            if mask >= 0b010101 {
+                mask += 1;
+            }
+            if mask >= 0b101010 {
+                mask += 1;
+            }
+            Some((2, 3, mask))
+        },
+        _ => None
+    }
+}
+
+/// Convert an image to characters that cover `columns`x`rows` pixels each.
+/// `glyph` receives a bitmask of which pixels (row by row, left to right)
+/// should be drawn in the foreground color.
+/// Cells with transparent pixels draw the rest on the terminal's background.
+pub fn cells(frame: &Frame, columns: u32, rows: u32, glyph: fn(u32) -> char) -> CellBuffer {
+    let width = frame.width().div_ceil(columns);
+    let rgb = |pixel: [u8; 3]| Color::Rgb(pixel[0], pixel[1], pixel[2]);
+    let lines = map_rows(frame.height().div_ceil(rows), |y| {
+        let mut line = Vec::with_capacity(width as usize);
+        let mut pixels = Vec::with_capacity((columns * rows) as usize);
+        let mut opaque = Vec::with_capacity(pixels.capacity());
+        for x in 0..width {
+            pixels.clear();
+            for dy in 0..rows {
+                for dx in 0..columns {
+                    let (px, py) = (x * columns + dx, y * rows + dy);
+                    if px < frame.width() && py < frame.height() {
+                        pixels.push(frame.visible(px, py));
+                    } else {
+                        pixels.push(None);
+                    }
+                }
+            }
+
+            line.push(if pixels.iter().all(Option::is_some) {
+                opaque.clear();
+                opaque.extend(pixels.iter().map(|pixel| pixel.unwrap()));
+                let (mask, fg, bg) = partition(&opaque);
+                Cell { glyph: glyph(mask), fg: rgb(fg), bg: rgb(bg), ..Cell::default() }
+            } else if pixels.iter().any(Option::is_some) {
+                // Only draw the opaque pixels, and leave the rest to the terminal
+                let mask = pixels.iter()
+                    .enumerate()
+                    .filter(|&(_, pixel)| pixel.is_some())
+                    .fold(0, |mask, (i, _)| mask | 1 << i);
+                opaque.clear();
+                opaque.extend(pixels.iter().filter_map(|&pixel| pixel));
+                let fg = average(sum(&opaque, |_| true));
+                Cell { glyph: glyph(mask), fg: rgb(fg), ..Cell::default() }
+            } else {
+                Cell::default()
+            });
+        }
+        line
+    });
+    CellBuffer::from_rows(width, lines)
+}
+
+/// Split pixels into the two groups that best represent them,
+/// by minimizing the squared error against each group's average.
+/// Returns a mask of pixels in the foreground group, and the foreground and background colors.
+pub fn partition(pixels: &[[u8; 3]]) -> (u32, [u8; 3], [u8; 3]) {
+    let total = sum(pixels, |_| true);
+
+    let mut best = (0, 0.0);
+    // The last pixel is always in the background, as inverting a mask gives the same split
+    for mask in 0..1u32 << (pixels.len() - 1) {
+        let fg = sum(pixels, |i| mask & (1 << i) != 0);
+        let bg = [total.0[0] - fg.0[0], total.0[1] - fg.0[1], total.0[2] - fg.0[2]];
+        let bg = (bg, total.1 - fg.1);
+
+        // Minimizing the squared error is the same as maximizing this
+        let score = spread(fg) + spread(bg);
+        if score > best.1 {
+            best = (mask, score);
+        }
+    }
+
+    let mask = best.0;
+    let bg = average(sum(pixels, |i| mask & (1 << i) == 0));
+    let fg = if mask == 0 { bg } else { average(sum(pixels, |i| mask & (1 << i) != 0)) };
+    (mask, fg, bg)
+}
+fn sum<F: Fn(usize) -> bool>(pixels: &[[u8; 3]], include: F) -> ([u32; 3], u32) {
+    let mut sum = [0; 3];
+    let mut count = 0;
+    for (_, pixel) in pixels.iter().enumerate().filter(|&(i, _)| include(i)) {
+        sum[0] += pixel[0] as u32;
+        sum[1] += pixel[1] as u32;
+        sum[2] += pixel[2] as u32;
+        count += 1;
+    }
+    (sum, count)
+}
+fn spread((sum, count): ([u32; 3], u32)) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    sum.iter().map(|&c| (c as f64).powi(2)).sum::<f64>() / count as f64
+}
+fn average((sum, count): ([u32; 3], u32)) -> [u8; 3] {
+    [
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_mask() {
+        for mask in 0..16 {
+            assert_eq!(block_mask(quadrant(mask)), Some((2, 2, mask)));
+        }
+        for mask in 0..64 {
+            let expected = match mask {
+                // Sextants that are also quadrants
+                0 => (2, 2, 0),
+                0b010101 => (2, 2, 0b0101),
+                0b101010 => (2, 2, 0b1010),
+                0b111111 => (2, 2, 0b1111),
+                mask => (2, 3, mask)
+            };
+            assert_eq!(block_mask(sextant(mask)), Some(expected));
+        }
+        assert_eq!(block_mask('a'), None);
+    }
+    #[test]
+    fn test_partition() {
+        let black = [0, 0, 0];
+        let white = [255, 255, 255];
+        assert_eq!(partition(&[white, black, black, black]), (0b0001, white, black));
+        assert_eq!(partition(&[black, white, white, black]), (0b0110, white, black));
+        assert_eq!(partition(&[black, black, black, black]), (0, black, black));
+        assert_eq!(partition(&[[10, 10, 10], [20, 20, 20], [200, 0, 0], [210, 0, 0]]), (0b0011, [15, 15, 15], [205, 0, 0]));
+    }
+}
@@ -0,0 +1,175 @@
+use super::{
+    cells::{Cell, CellBuffer},
+    frame::Frame,
+    sgr::Color
+};
+
+use image::{GenericImage, Pixel};
+use std::{
+    char,
+    io::{self, Write}
+};
+
+/// The bit of each dot in a braille cell, indexed by [y][x]
+const DOTS: [[u32; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80]
+];
+/// A 4x4 Bayer matrix used for ordered dithering
+const BAYER: [[u8; 4]; 4] = [
+    [ 0,  8,  2, 10],
+    [12,  4, 14,  6],
+    [ 3, 11,  1,  9],
+    [15,  7, 13,  5]
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Braille {
+    /// The luminance a pixel needs to be above to raise a dot
+    pub threshold: u8,
+    /// Use ordered dithering instead of a fixed threshold
+    pub dither: bool,
+    /// Color each cell with the average of its raised dots
+    pub color: bool
+}
+impl Default for Braille {
+    fn default() -> Self {
+        Self {
+            threshold: 128,
+            dither: false,
+            color: true
+        }
+    }
+}
+
+impl Braille {
+    /// Convert an image to a grid of cells, each showing 2x4 pixels
+    pub fn cells<I, P>(&self, image: &I) -> CellBuffer
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.frame_cells(&Frame::new(image))
+    }
+    /// Like `cells`, but reads the raw pixels directly
+    pub fn frame_cells(&self, frame: &Frame) -> CellBuffer {
+        let mut cells = CellBuffer::new(frame.width().div_ceil(2), frame.height().div_ceil(4));
+        for y in 0..cells.height() {
+            for x in 0..cells.width() {
+                let mut glyph = 0;
+                let mut sum = [0u32; 3];
+                let mut raised = 0;
+
+                for (dy, row) in DOTS.iter().enumerate() {
+                    for (dx, &bit) in row.iter().enumerate() {
+                        let (px, py) = (x * 2 + dx as u32, y * 4 + dy as u32);
+                        if px >= frame.width() || py >= frame.height() {
+                            continue;
+                        }
+                        let pixel = match frame.visible(px, py) {
+                            Some(pixel) => pixel,
+                            None => continue
+                        };
+                        let luma = (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000;
+                        let threshold = if self.dither {
+                            BAYER[py as usize % 4][px as usize % 4] as u32 * 16 + 8
+                        } else {
+                            self.threshold as u32
+                        };
+                        if luma > threshold {
+                            glyph |= bit;
+                            raised += 1;
+                            for i in 0..3 {
+                                sum[i] += pixel[i] as u32;
+                            }
+                        }
+                    }
+                }
+
+                let fg = if self.color && raised > 0 {
+                    Color::Rgb((sum[0] / raised) as u8, (sum[1] / raised) as u8, (sum[2] / raised) as u8)
+                } else {
+                    Color::Default
+                };
+                *cells.get_mut(x, y) = Cell {
+                    glyph: char::from_u32(0x2800 + glyph).unwrap(),
+                    fg,
+                    ..Cell::default()
+                };
+            }
+        }
+        cells
+    }
+}
+
+impl super::Converter for Braille {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.cells(image).write_ansi(fmt, false)
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        Some(self.cells(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.frame_cells(frame).write_ansi(fmt, false)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        Some(self.frame_cells(frame))
+    }
+
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        (x * 2, y * 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_dots() {
+        // Light up one dot at a time, and check it ends up in the right bit
+        for (y, row) in DOTS.iter().enumerate() {
+            for (x, &bit) in row.iter().enumerate() {
+                let image = RgbaImage::from_fn(2, 4, |px, py| {
+                    if (px, py) == (x as u32, y as u32) { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 255]) }
+                });
+                let cells = Braille::default().cells(&image);
+                assert_eq!(cells.get(0, 0).glyph, char::from_u32(0x2800 + bit).unwrap());
+            }
+        }
+    }
+    #[test]
+    fn test_threshold() {
+        let mut image = RgbaImage::from_pixel(2, 4, Rgba([0, 0, 0, 255]));
+        image.put_pixel(0, 0, Rgba([255, 255, 255, 255]));
+        image.put_pixel(1, 3, Rgba([200, 200, 100, 255]));
+        // Transparent pixels never raise a dot
+        image.put_pixel(1, 0, Rgba([255, 255, 255, 0]));
+
+        // The color is the average of the raised dots only
+        let cell = *Braille::default().cells(&image).get(0, 0);
+        assert_eq!(cell.glyph, '\u{2881}');
+        assert_eq!(cell.fg, Color::Rgb(227, 227, 177));
+
+        let cell = *Braille { color: false, ..Braille::default() }.cells(&image).get(0, 0);
+        assert_eq!(cell.fg, Color::Default);
+
+        let cell = *Braille { threshold: 250, ..Braille::default() }.cells(&image).get(0, 0);
+        assert_eq!(cell.glyph, '\u{2801}');
+
+        // With dithering, a flat gray raises the dots whose Bayer threshold is below it
+        let gray = RgbaImage::from_pixel(2, 4, Rgba([100, 100, 100, 255]));
+        let cell = *Braille { dither: true, ..Braille::default() }.cells(&gray).get(0, 0);
+        assert_eq!(cell.glyph, '\u{2815}');
+    }
+}
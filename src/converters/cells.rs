@@ -0,0 +1,542 @@
+//! A grid of colored characters, which text converters produce before writing escape sequences
+
+use super::{
+    blocks::block_mask,
+    frame::{map_rows, Frame},
+    sgr::{Color, SgrWriter},
+    HalfBlock, TrueColor
+};
+
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    slice
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Text attributes of a cell
+pub struct Attrs {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A single character on the terminal
+pub struct Cell {
+    pub glyph: char,
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: Attrs
+}
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            glyph: ' ',
+            fg: Color::Default,
+            bg: Color::Default,
+            attrs: Attrs::default()
+        }
+    }
+}
+impl Cell {
+    /// Return true if the cell only shows the background, so the foreground doesn't matter
+    pub fn is_blank(&self) -> bool {
+        is_blank(self.glyph) && !self.attrs.underline && !self.attrs.reverse
+    }
+    /// Return true if the cells look different.
+    /// RGB colors count as the same if no channel differs by more than `tolerance`.
+    pub fn differs(&self, other: &Cell, tolerance: u8) -> bool {
+        self.glyph != other.glyph
+            || self.attrs != other.attrs
+            || (!self.is_blank() && color_differs(self.fg, other.fg, tolerance))
+            || color_differs(self.bg, other.bg, tolerance)
+    }
+}
+/// Return true for glyphs that only show the background
+pub fn is_blank(glyph: char) -> bool {
+    glyph == ' ' || glyph == '\u{2800}'
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// How `CellBuffer::write_html` styles the cells
+pub enum HtmlStyle {
+    /// A style attribute on each span, which survives being pasted anywhere
+    #[default]
+    Inline,
+    /// A `<style>` element with a class for each color. Smaller for images with few colors,
+    /// like pixel art, but photos have so many colors that the style sheet makes them bigger
+    Classes
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// How converters that draw cells somewhere else than a terminal, like `Html` and `Svg`,
+/// turn pixels into cells
+pub enum CellLayout {
+    /// Two pixels per character, like `HalfBlock`
+    #[default]
+    HalfBlock,
+    /// One pixel per character, like `TrueColor`
+    TrueColor
+}
+impl CellLayout {
+    /// Convert raw pixels to a grid of cells
+    pub fn frame_cells(self, frame: &Frame) -> CellBuffer {
+        match self {
+            CellLayout::HalfBlock => HalfBlock::default().frame_cells(frame),
+            CellLayout::TrueColor => TrueColor::default().frame_cells(frame)
+        }
+    }
+    /// Where is x/y in the cells on the image? See `Converter::actual_pos`.
+    pub fn actual_pos(self, x: u32, y: u32) -> (u32, u32) {
+        match self {
+            CellLayout::HalfBlock => (x, y * 2),
+            CellLayout::TrueColor => (x, y)
+        }
+    }
+}
+
+/// Return the CSS classes for a cell's colors and attributes,
+/// together with the declaration each of them stands for
+fn html_classes(cell: &Cell) -> Vec<(String, String)> {
+    let (fg, bg) = shown_colors(cell);
+    let mut classes = Vec::new();
+    if let (false, Some(fg)) = (cell.is_blank(), fg.to_rgb()) {
+        let hex = hex(fg);
+        classes.push((format!("tp-fg-{}", &hex[1..]), format!("color:{};", hex)));
+    }
+    if let Some(bg) = bg.to_rgb() {
+        let hex = hex(bg);
+        classes.push((format!("tp-bg-{}", &hex[1..]), format!("background-color:{};", hex)));
+    }
+    for &(on, class, declaration) in &[
+        (cell.attrs.bold, "tp-b", "font-weight:bold;"),
+        (cell.attrs.italic, "tp-i", "font-style:italic;"),
+        (cell.attrs.underline, "tp-u", "text-decoration:underline;")
+    ] {
+        if on {
+            classes.push((class.to_string(), declaration.to_string()));
+        }
+    }
+    classes
+}
+/// Return the foreground and background a cell is shown with, after reversing them if needed
+fn shown_colors(cell: &Cell) -> (Color, Color) {
+    if cell.attrs.reverse {
+        // The default colors are unknown, so guess they're black and white
+        (
+            if cell.bg == Color::Default { Color::Rgb(0, 0, 0) } else { cell.bg },
+            if cell.fg == Color::Default { Color::Rgb(255, 255, 255) } else { cell.fg }
+        )
+    } else {
+        (cell.fg, cell.bg)
+    }
+}
+/// Write a glyph as HTML or SVG text
+fn write_escaped<W: Write>(fmt: &mut W, glyph: char) -> io::Result<()> {
+    match glyph {
+        '<' => write!(fmt, "&lt;"),
+        '>' => write!(fmt, "&gt;"),
+        '&' => write!(fmt, "&amp;"),
+        glyph => write!(fmt, "{}", glyph)
+    }
+}
+/// Format a color for HTML and SVG
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+/// Format a length for SVG, without needless decimals
+fn length(value: f64) -> String {
+    let value = format!("{:.2}", value);
+    value.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+fn color_differs(a: Color, b: Color, tolerance: u8) -> bool {
+    match (a, b) {
+        (Color::Rgb(r1, g1, b1), Color::Rgb(r2, g2, b2)) => {
+            let diff = |a: u8, b: u8| (a as i16 - b as i16).unsigned_abs() as u8;
+            diff(r1, r2) > tolerance || diff(g1, g2) > tolerance || diff(b1, b2) > tolerance
+        },
+        (a, b) => a != b
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// A grid of cells, stored row by row
+pub struct CellBuffer {
+    width: u32,
+    height: u32,
+    cells: Vec<Cell>
+}
+impl CellBuffer {
+    /// Create a buffer of blank cells
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); (width * height) as usize]
+        }
+    }
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+
+    /// Create a buffer from rows of cells, which must all be `width` long
+    pub fn from_rows(width: u32, rows: Vec<Vec<Cell>>) -> Self {
+        assert!(rows.iter().all(|row| row.len() == width as usize), "rows must all be {} cells long", width);
+        Self {
+            width,
+            height: rows.len() as u32,
+            cells: rows.concat()
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &Cell {
+        &self.cells[(y * self.width + x) as usize]
+    }
+    pub fn get_mut(&mut self, x: u32, y: u32) -> &mut Cell {
+        &mut self.cells[(y * self.width + x) as usize]
+    }
+    pub fn row(&self, y: u32) -> &[Cell] {
+        let start = (y * self.width) as usize;
+        &self.cells[start..start + self.width as usize]
+    }
+    /// Iterate over the rows of the buffer
+    pub fn rows(&self) -> slice::Chunks<'_, Cell> {
+        self.cells.chunks(self.width.max(1) as usize)
+    }
+    /// Return true if any cell has a color or attribute, and not just the terminal's default
+    pub fn has_style(&self) -> bool {
+        self.cells.iter().any(|cell| {
+            cell.bg != Color::Default
+                || (!cell.is_blank() && cell.fg != Color::Default)
+                || cell.attrs != Attrs::default()
+        })
+    }
+
+    /// Write the whole buffer, starting at the cursor.
+    /// Buffers without any style are written as plain text, like `write_plain`,
+    /// so they can end up in logs or files just as well.
+    /// With the `rayon` feature, rows are serialized in parallel.
+    pub fn write_ansi<W: Write>(&self, fmt: &mut W, rep: bool) -> io::Result<()> {
+        if !self.has_style() {
+            return self.write_plain(fmt);
+        }
+
+        let rows = map_rows(self.height, |y| -> io::Result<Vec<u8>> {
+            let mut buf = Vec::new();
+            {
+                let mut fmt = SgrWriter::new(&mut buf, rep);
+                if y > 0 {
+                    fmt.assume_reset();
+                }
+                for cell in self.row(y) {
+                    fmt.cell(cell)?;
+                }
+                fmt.reset()?;
+            }
+            Ok(buf)
+        });
+        for (y, row) in rows.into_iter().enumerate() {
+            if y > 0 {
+                write!(fmt, "\r\n")?;
+            }
+            fmt.write_all(&row?)?;
+        }
+        Ok(())
+    }
+    /// Write just the characters, one line per row
+    pub fn write_plain<W: Write>(&self, fmt: &mut W) -> io::Result<()> {
+        for (y, row) in self.rows().enumerate() {
+            if y > 0 {
+                writeln!(fmt)?;
+            }
+            for cell in row {
+                write!(fmt, "{}", cell.glyph)?;
+            }
+        }
+        Ok(())
+    }
+    /// Write the buffer as a HTML `<pre>` element.
+    /// Indexed colors are shown like xterm's palette,
+    /// and default colors are left to the surrounding page.
+    pub fn write_html<W: Write>(&self, fmt: &mut W, style: HtmlStyle) -> io::Result<()> {
+        match style {
+            HtmlStyle::Inline => write!(fmt, "<pre style=\"line-height:1\">")?,
+            HtmlStyle::Classes => {
+                // The set finds duplicates quickly, while the list keeps them in order
+                let mut seen = HashSet::new();
+                let mut classes: Vec<(String, String)> = Vec::new();
+                for cell in &self.cells {
+                    for class in html_classes(cell) {
+                        if seen.insert(class.0.clone()) {
+                            classes.push(class);
+                        }
+                    }
+                }
+                write!(fmt, "<style>.termplay{{line-height:1}}")?;
+                for (class, declaration) in classes {
+                    write!(fmt, ".{}{{{}}}", class, declaration)?;
+                }
+                write!(fmt, "</style><pre class=\"termplay\">")?;
+            }
+        }
+        for (y, row) in self.rows().enumerate() {
+            if y > 0 {
+                writeln!(fmt)?;
+            }
+            // Cells with the same style share a span
+            let mut start = 0;
+            while start < row.len() {
+                let classes = html_classes(&row[start]);
+                let len = row[start..].iter().take_while(|cell| html_classes(cell) == classes).count();
+                if !classes.is_empty() {
+                    match style {
+                        HtmlStyle::Inline => {
+                            let declarations: Vec<_> = classes.iter().map(|class| &*class.1).collect();
+                            write!(fmt, "<span style=\"{}\">", declarations.concat())?;
+                        },
+                        HtmlStyle::Classes => {
+                            let names: Vec<_> = classes.iter().map(|class| &*class.0).collect();
+                            write!(fmt, "<span class=\"{}\">", names.join(" "))?;
+                        }
+                    }
+                }
+                for cell in &row[start..start + len] {
+                    write_escaped(fmt, cell.glyph)?;
+                }
+                if !classes.is_empty() {
+                    write!(fmt, "</span>")?;
+                }
+                start += len;
+            }
+        }
+        write!(fmt, "</pre>")
+    }
+    /// Write the buffer as a SVG image, with each cell `cell_width`x`cell_height` pixels big.
+    /// Block glyphs like halfblocks are drawn as rectangles, so they line up exactly,
+    /// and other glyphs as text in the default monospace font.
+    /// Default colors are left transparent for the background, and black for the foreground.
+    pub fn write_svg<W: Write>(&self, fmt: &mut W, cell_width: u32, cell_height: u32) -> io::Result<()> {
+        let (width, height) = (self.width * cell_width, self.height * cell_height);
+        write!(
+            fmt,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" \
+             font-family=\"monospace\" font-size=\"{2}\" shape-rendering=\"crispEdges\">",
+            width, height, cell_height
+        )?;
+        for (y, row) in self.rows().enumerate() {
+            let top = y as u32 * cell_height;
+
+            // Backgrounds first, with runs of the same color sharing a rect
+            let mut start = 0;
+            while start < row.len() {
+                let bg = shown_colors(&row[start]).1;
+                let len = row[start..].iter().take_while(|cell| shown_colors(cell).1 == bg).count();
+                if let Some(bg) = bg.to_rgb() {
+                    write!(
+                        fmt,
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                        start as u32 * cell_width, top, len as u32 * cell_width, cell_height, hex(bg)
+                    )?;
+                }
+                start += len;
+            }
+
+            for (x, cell) in row.iter().enumerate() {
+                if cell.is_blank() {
+                    continue;
+                }
+                let left = x as u32 * cell_width;
+                let fg = hex(shown_colors(cell).0.to_rgb().unwrap_or((0, 0, 0)));
+                match block_mask(cell.glyph) {
+                    Some((columns, rows, mask)) => {
+                        let (width, height) = (cell_width as f64 / columns as f64, cell_height as f64 / rows as f64);
+                        for i in (0..columns * rows).filter(|i| mask & (1 << i) != 0) {
+                            write!(
+                                fmt,
+                                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                                length(left as f64 + (i % columns) as f64 * width),
+                                length(top as f64 + (i / columns) as f64 * height),
+                                length(width), length(height), fg
+                            )?;
+                        }
+                    },
+                    None => {
+                        write!(fmt, "<text x=\"{}\" y=\"{}\" fill=\"{}\"", left, length(top as f64 + cell_height as f64 * 0.8), fg)?;
+                        if cell.attrs.bold {
+                            write!(fmt, " font-weight=\"bold\"")?;
+                        }
+                        if cell.attrs.italic {
+                            write!(fmt, " font-style=\"italic\"")?;
+                        }
+                        if cell.attrs.underline {
+                            write!(fmt, " text-decoration=\"underline\"")?;
+                        }
+                        write!(fmt, ">")?;
+                        write_escaped(fmt, cell.glyph)?;
+                        write!(fmt, "</text>")?;
+                    }
+                }
+            }
+        }
+        write!(fmt, "</svg>")
+    }
+    /// Write only the cells that differ from `screen` by more than `tolerance`,
+    /// moving the cursor to each of them. `origin` is where the top left cell goes, counting from 1.
+    /// `screen` is what's currently on the screen, and gets updated with what was written.
+    /// If it's None or a different size, everything is written.
+    /// With `rep`, runs of the same cell are shortened with REP, see `SgrWriter`.
+    pub fn write_diff<W: Write>(
+        &self,
+        fmt: &mut W,
+        screen: &mut Option<CellBuffer>,
+        origin: (u16, u16),
+        tolerance: u8,
+        rep: bool
+    ) -> io::Result<()> {
+        let redraw = match *screen {
+            Some(ref screen) => screen.width != self.width || screen.height != self.height,
+            None => true
+        };
+        if redraw {
+            *screen = Some(CellBuffer {
+                width: self.width,
+                height: self.height,
+                cells: Vec::new()
+            });
+        }
+        let screen = screen.as_mut().unwrap();
+
+        let mut fmt = SgrWriter::new(fmt, rep);
+        let mut cursor = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = *self.get(x, y);
+                if !redraw && !cell.differs(screen.get(x, y), tolerance) {
+                    continue;
+                }
+                if cursor != Some((x, y)) {
+                    fmt.goto(origin.0 as u32 + x, origin.1 as u32 + y)?;
+                }
+                fmt.cell(&cell)?;
+                cursor = Some((x + 1, y));
+
+                if !redraw {
+                    *screen.get_mut(x, y) = cell;
+                }
+            }
+        }
+        if redraw {
+            screen.cells = self.cells.clone();
+        }
+        if cursor.is_some() {
+            fmt.reset()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converters::{Converter, HalfBlock};
+
+    use image::RgbaImage;
+
+    fn buffer(colors: &[u8]) -> CellBuffer {
+        let mut buffer = CellBuffer::new(colors.len() as u32, 1);
+        for (x, &color) in colors.iter().enumerate() {
+            buffer.get_mut(x as u32, 0).bg = Color::Rgb(color, color, color);
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_serializers() {
+        let mut buffer = CellBuffer::new(3, 2);
+        *buffer.get_mut(0, 0) = Cell { glyph: '▀', fg: Color::Rgb(255, 0, 0), bg: Color::Indexed(4), ..Cell::default() };
+        *buffer.get_mut(1, 0) = Cell { glyph: '▀', fg: Color::Rgb(255, 0, 0), bg: Color::Indexed(4), ..Cell::default() };
+        *buffer.get_mut(0, 1) = Cell { glyph: '<', attrs: Attrs { bold: true, ..Attrs::default() }, ..Cell::default() };
+        buffer.get_mut(2, 1).bg = Color::Indexed(196);
+
+        let mut ansi = Vec::new();
+        buffer.write_ansi(&mut ansi, false).unwrap();
+        assert_eq!(
+            String::from_utf8(ansi).unwrap(),
+            "\x1b[38;2;255;0;0;44m▀▀\x1b[49m \x1b[0m\r\n\
+             \x1b[1m<\x1b[22m \x1b[48;5;196m \x1b[0m"
+        );
+
+        let mut html = Vec::new();
+        buffer.write_html(&mut html, HtmlStyle::Inline).unwrap();
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            "<pre style=\"line-height:1\"><span style=\"color:#ff0000;background-color:#000080;\">▀▀</span> \n\
+             <span style=\"font-weight:bold;\">&lt;</span> <span style=\"background-color:#ff0000;\"> </span></pre>"
+        );
+
+        let mut html = Vec::new();
+        buffer.write_html(&mut html, HtmlStyle::Classes).unwrap();
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            "<style>.termplay{line-height:1}\
+             .tp-fg-ff0000{color:#ff0000;}.tp-bg-000080{background-color:#000080;}\
+             .tp-b{font-weight:bold;}.tp-bg-ff0000{background-color:#ff0000;}</style>\
+             <pre class=\"termplay\"><span class=\"tp-fg-ff0000 tp-bg-000080\">▀▀</span> \n\
+             <span class=\"tp-b\">&lt;</span> <span class=\"tp-bg-ff0000\"> </span></pre>"
+        );
+
+        let mut svg = Vec::new();
+        buffer.write_svg(&mut svg, 8, 16).unwrap();
+        assert_eq!(
+            String::from_utf8(svg).unwrap(),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"24\" height=\"32\" viewBox=\"0 0 24 32\" \
+             font-family=\"monospace\" font-size=\"16\" shape-rendering=\"crispEdges\">\
+             <rect x=\"0\" y=\"0\" width=\"16\" height=\"16\" fill=\"#000080\"/>\
+             <rect x=\"0\" y=\"0\" width=\"4\" height=\"8\" fill=\"#ff0000\"/>\
+             <rect x=\"4\" y=\"0\" width=\"4\" height=\"8\" fill=\"#ff0000\"/>\
+             <rect x=\"8\" y=\"0\" width=\"4\" height=\"8\" fill=\"#ff0000\"/>\
+             <rect x=\"12\" y=\"0\" width=\"4\" height=\"8\" fill=\"#ff0000\"/>\
+             <rect x=\"16\" y=\"16\" width=\"8\" height=\"16\" fill=\"#ff0000\"/>\
+             <text x=\"0\" y=\"28.8\" fill=\"#000000\" font-weight=\"bold\">&lt;</text></svg>"
+        );
+
+        let mut plain = Vec::new();
+        buffer.write_plain(&mut plain).unwrap();
+        assert_eq!(String::from_utf8(plain).unwrap(), "▀▀ \n<  ");
+    }
+    #[test]
+    fn test_halfblock() {
+        let image = RgbaImage::from_raw(2, 2, vec![
+            255, 0, 0, 255,   0, 0, 0, 0,
+            0, 0, 255, 255,   0, 255, 0, 255
+        ]).unwrap();
+        let cells = HalfBlock::default().to_cells(&image).unwrap();
+        assert_eq!(cells.width(), 2);
+        assert_eq!(cells.height(), 1);
+        assert_eq!(*cells.get(0, 0), Cell { glyph: '▀', fg: Color::Rgb(255, 0, 0), bg: Color::Rgb(0, 0, 255), ..Cell::default() });
+        assert_eq!(*cells.get(1, 0), Cell { glyph: '▄', fg: Color::Rgb(0, 255, 0), ..Cell::default() });
+    }
+    #[test]
+    fn test_write_diff() {
+        let mut screen = None;
+        let mut out = Vec::new();
+        buffer(&[0, 0, 0, 0]).write_diff(&mut out, &mut screen, (3, 2), 0, false).unwrap();
+        assert_eq!(out, b"\x1b[2;3H\x1b[48;2;0;0;0m    \x1b[0m");
+
+        // Small changes are left alone, but still add up over time
+        out.clear();
+        buffer(&[4, 0, 30, 20]).write_diff(&mut out, &mut screen, (3, 2), 5, false).unwrap();
+        assert_eq!(out, b"\x1b[2;5H\x1b[48;2;30;30;30m \x1b[48;2;20;20;20m \x1b[0m");
+        out.clear();
+        buffer(&[8, 0, 30, 20]).write_diff(&mut out, &mut screen, (3, 2), 5, false).unwrap();
+        assert_eq!(out, b"\x1b[2;3H\x1b[48;2;8;8;8m \x1b[0m");
+        assert_eq!(screen, Some(buffer(&[8, 0, 30, 20])));
+
+        out.clear();
+        buffer(&[8, 0, 30, 20]).write_diff(&mut out, &mut screen, (3, 2), 0, false).unwrap();
+        assert!(out.is_empty());
+
+        out.clear();
+        buffer(&[0; 8]).write_diff(&mut out, &mut None, (1, 1), 0, true).unwrap();
+        assert_eq!(out, b"\x1b[1;1H\x1b[48;2;0;0;0m \x1b[7b\x1b[0m");
+    }
+}
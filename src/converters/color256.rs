@@ -1,11 +1,66 @@
+use super::{
+    cells::CellBuffer,
+    frame::Frame,
+    sgr::Color,
+    ColorMetric, Dither, Quantizer
+};
+
 use image::{GenericImage, Pixel};
 use std::{
     cmp::{min, max},
-    io::{self, Write}
+    io::{self, Write},
+    sync::Arc
 };
 
-#[derive(Clone, Copy, Debug)]
-pub struct Color256;
+#[derive(Clone, Debug, Default)]
+pub struct Color256 {
+    /// How to find the closest color in the palette
+    pub metric: ColorMetric,
+    pub dither: Dither,
+    /// A quantizer for a different palette, like one queried from the terminal.
+    /// Either way, colors are matched approximately, see `Quantizer`.
+    /// When set, this is used instead of `metric` and the xterm colors.
+    pub quantizer: Option<Arc<Quantizer>>,
+    /// Shorten runs of the same color with REP, which not all terminals support
+    pub rep: bool
+}
+
+impl Color256 {
+    /// Convert an image to a grid of cells, one for each pixel
+    pub fn cells<I, P>(&self, image: &I) -> CellBuffer
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.frame_cells(&Frame::new(image))
+    }
+    /// Like `cells`, but reads the raw pixels directly
+    pub fn frame_cells(&self, frame: &Frame) -> CellBuffer {
+        let width = frame.width() as usize;
+        let mut pixels = Vec::with_capacity(width * frame.height() as usize);
+        let mut opaque = Vec::with_capacity(pixels.capacity());
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                let pixel = frame.visible(x, y);
+                pixels.push(pixel.unwrap_or([0, 0, 0]));
+                opaque.push(pixel.is_some());
+            }
+        }
+
+        let quantizer = match self.quantizer {
+            Some(ref quantizer) => quantizer,
+            None => Quantizer::xterm(self.metric)
+        };
+        let indices = self.dither.apply(&pixels, width, quantizer.palette(), |r, g, b| quantizer.lookup(r, g, b));
+
+        let mut cells = CellBuffer::new(frame.width(), frame.height());
+        for (i, (&index, &opaque)) in indices.iter().zip(&opaque).enumerate() {
+            if opaque {
+                cells.get_mut((i % width) as u32, (i / width) as u32).bg = Color::Indexed(index);
+            }
+        }
+        cells
+    }
+}
 
 impl super::Converter for Color256 {
     fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
@@ -13,24 +68,37 @@ impl super::Converter for Color256 {
               I: GenericImage<Pixel = P>,
               P: Pixel<Subpixel = u8>
     {
-        for y in 0..image.height() {
-            if y > 0 {
-                write!(fmt, "\r\n")?;
-            }
-            for x in 0..image.width() {
-                let pixel = image.get_pixel(x, y).to_rgb().0;
-                write!(fmt, "\x1b[48;5;{}m ", lookup_color(pixel[0], pixel[1], pixel[2]))?;
-            }
-            write!(fmt, "\x1b[0m")?;
-        }
-        Ok(())
+        self.cells(image).write_ansi(fmt, self.rep)
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        Some(self.cells(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.frame_cells(frame).write_ansi(fmt, self.rep)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        Some(self.frame_cells(frame))
     }
 }
 
 pub fn lookup_color(r: u8, g: u8, b: u8) -> u8 {
+    closest(COLORS, r, g, b)
+}
+/// Like lookup_color, but lets you choose how colors are compared
+pub fn lookup_color_with(metric: ColorMetric, r: u8, g: u8, b: u8) -> u8 {
+    Quantizer::xterm(metric).closest(r, g, b)
+}
+/// Like lookup_color, but only considers the 16 standard colors
+pub fn lookup_color_16(r: u8, g: u8, b: u8) -> u8 {
+    closest(&COLORS[..16], r, g, b)
+}
+fn closest(colors: &[(u8, u8, u8)], r: u8, g: u8, b: u8) -> u8 {
     let mut min: (u8, u32) = (0, ::std::u32::MAX);
 
-    for (i, &(r2, g2, b2)) in COLORS.iter().enumerate() {
+    for (i, &(r2, g2, b2)) in colors.iter().enumerate() {
         let diff: u32 = abs_sub(r, r2) as u32 + abs_sub(g, g2) as u32 + abs_sub(b, b2) as u32;
         if diff < min.1 {
             min = (i as u8, diff);
@@ -43,7 +111,8 @@ fn abs_sub(x: u8, y: u8) -> u8 {
     max(x, y) - min(x, y)
 }
 
-const COLORS: &[(u8, u8, u8)] = &[
+/// The default xterm 256 color palette
+pub const COLORS: &[(u8, u8, u8)] = &[
     (0,0,0),
     (128,0,0),
     (0,128,0),
@@ -301,3 +370,27 @@ const COLORS: &[(u8, u8, u8)] = &[
     (228,228,228),
     (238,238,238)
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_colors() {
+        let metrics = [ColorMetric::Manhattan, ColorMetric::Cie76, ColorMetric::Ciede2000, ColorMetric::Oklab];
+        for &metric in &metrics {
+            assert_eq!(lookup_color_with(metric, 0, 0, 0), 0);
+            assert_eq!(lookup_color_with(metric, 255, 0, 0), 9);
+            assert_eq!(lookup_color_with(metric, 95, 135, 175), 67);
+            assert_eq!(lookup_color_with(metric, 215, 175, 135), 180);
+            assert_eq!(lookup_color_with(metric, 138, 138, 138), 245);
+        }
+    }
+    #[test]
+    fn test_skin_tone() {
+        // Manhattan picks a much too red color, while CIELAB keeps the hue
+        assert_eq!(lookup_color_with(ColorMetric::Manhattan, 241, 194, 125), 216);
+        assert_eq!(lookup_color_with(ColorMetric::Cie76, 241, 194, 125), 222);
+        assert_eq!(lookup_color_with(ColorMetric::Ciede2000, 241, 194, 125), 222);
+    }
+}
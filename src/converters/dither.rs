@@ -0,0 +1,125 @@
+//! Dithering for converters with a limited palette
+
+/// An 8x8 Bayer matrix for ordered dithering
+const BAYER: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21]
+];
+/// How far ordered dithering may move a channel.
+/// About the distance between two steps in the 256 color cube.
+const BAYER_SPREAD: f32 = 40.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// How to spread out the error of mapping colors to a palette
+pub enum Dither {
+    /// Pick the closest color for each pixel
+    #[default]
+    None,
+    /// Floyd–Steinberg error diffusion
+    FloydSteinberg,
+    /// Atkinson error diffusion, which only spreads 3/4 of the error for more contrast
+    Atkinson,
+    /// Ordered dithering with a Bayer matrix.
+    /// Each pixel is handled on its own, so this is recommended for video
+    /// where error diffusion would flicker between frames.
+    Bayer
+}
+impl Dither {
+    /// Map each pixel of an image that is `width` pixels wide to a palette index.
+    /// `nearest` returns the index of the closest palette color.
+    pub fn apply<F>(self, pixels: &[[u8; 3]], width: usize, palette: &[(u8, u8, u8)], mut nearest: F) -> Vec<u8>
+        where F: FnMut(u8, u8, u8) -> u8
+    {
+        let (weights, divisor): (&[(isize, usize, f32)], f32) = match self {
+            Dither::None => {
+                return pixels.iter().map(|p| nearest(p[0], p[1], p[2])).collect();
+            },
+            Dither::Bayer => {
+                return pixels.iter()
+                    .enumerate()
+                    .map(|(i, p)| {
+                        let threshold = BAYER[(i / width) % 8][(i % width) % 8] as f32;
+                        let offset = ((threshold + 0.5) / 64.0 - 0.5) * BAYER_SPREAD;
+                        let channel = |c: u8| (c as f32 + offset).clamp(0.0, 255.0) as u8;
+                        nearest(channel(p[0]), channel(p[1]), channel(p[2]))
+                    })
+                    .collect();
+            },
+            Dither::FloydSteinberg => (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0),
+            Dither::Atkinson => (&[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)], 8.0)
+        };
+
+        let mut work: Vec<[f32; 3]> = pixels.iter()
+            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+            .collect();
+        let height = pixels.len().checked_div(width).unwrap_or(0);
+        let mut indices = Vec::with_capacity(pixels.len());
+
+        for y in 0..height {
+            for x in 0..width {
+                let old = work[y * width + x];
+                let channel = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+                let index = nearest(channel(old[0]), channel(old[1]), channel(old[2]));
+                indices.push(index);
+
+                let new = palette[index as usize];
+                let error = [old[0] - new.0 as f32, old[1] - new.1 as f32, old[2] - new.2 as f32];
+
+                for &(dx, dy, weight) in weights {
+                    let (nx, ny) = (x as isize + dx, y + dy);
+                    if nx < 0 || nx as usize >= width || ny >= height {
+                        continue;
+                    }
+                    let target = &mut work[ny * width + nx as usize];
+                    for c in 0..3 {
+                        target[c] += error[c] * weight / divisor;
+                    }
+                }
+            }
+        }
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nearest(palette: &[(u8, u8, u8)]) -> impl Fn(u8, u8, u8) -> u8 + '_ {
+        move |r, _, _| if (r as u32) < palette[1].0 as u32 / 2 { 0 } else { 1 }
+    }
+
+    #[test]
+    fn test_dither() {
+        let palette = [(0, 0, 0), (255, 255, 255)];
+        let gray = vec![[128, 128, 128]; 16];
+
+        assert_eq!(Dither::None.apply(&gray, 4, &palette, nearest(&palette)), vec![1; 16]);
+
+        // Half of the pixels should end up white
+        for &dither in &[Dither::FloydSteinberg, Dither::Bayer] {
+            let indices = dither.apply(&gray, 4, &palette, nearest(&palette));
+            assert_eq!(indices.iter().filter(|&&i| i == 1).count(), 8, "{:?}", dither);
+        }
+    }
+
+    #[test]
+    fn test_atkinson() {
+        let palette = [(0, 0, 0), (255, 255, 255)];
+        let gray = vec![[128, 128, 128]; 64];
+
+        // Atkinson drops a quarter of the error, so the mean can drift a little,
+        // but it should still land close to the input
+        let indices = Dither::Atkinson.apply(&gray, 8, &palette, nearest(&palette));
+        let white = indices.iter().filter(|&&i| i == 1).count();
+        assert!(white > 0 && white < 64, "{:?}", indices);
+        let mean = white as f32 * 255.0 / 64.0;
+        assert!((mean - 128.0).abs() < 255.0 / 8.0, "mean {}", mean);
+    }
+}
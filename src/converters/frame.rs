@@ -0,0 +1,140 @@
+//! Fast access to raw pixels, and rendering rows in parallel with the `rayon` feature
+
+use super::alpha::ALPHA_THRESHOLD;
+
+use image::{DynamicImage, GenericImageView, Pixel, Rgba, RgbImage, RgbaImage};
+#[cfg(feature = "rayon")] use rayon::prelude::*;
+use std::borrow::Cow;
+
+#[derive(Clone, Debug)]
+/// The raw pixels of an image, in RGB or RGBA.
+/// Unlike `GenericImage`, this can be shared between threads,
+/// and reading a pixel is just indexing into a slice.
+pub struct Frame<'a> {
+    width: u32,
+    height: u32,
+    channels: usize,
+    data: Cow<'a, [u8]>
+}
+impl Frame<'static> {
+    /// Copy the pixels of any image
+    pub fn new<I, P>(image: &I) -> Self
+        where I: GenericImageView<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        let mut data = Vec::with_capacity(image.width() as usize * image.height() as usize * 4);
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                data.extend_from_slice(&image.get_pixel(x, y).to_rgba().0);
+            }
+        }
+        Self {
+            width: image.width(),
+            height: image.height(),
+            channels: 4,
+            data: Cow::Owned(data)
+        }
+    }
+}
+impl<'a> Frame<'a> {
+    /// Use the pixels of an RGB image without copying them
+    pub fn from_rgb(image: &'a RgbImage) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            channels: 3,
+            data: Cow::Borrowed(image.as_raw())
+        }
+    }
+    /// Use the pixels of an RGBA image without copying them
+    pub fn from_rgba(image: &'a RgbaImage) -> Self {
+        Self {
+            width: image.width(),
+            height: image.height(),
+            channels: 4,
+            data: Cow::Borrowed(image.as_raw())
+        }
+    }
+    /// Use the pixels of an image without copying them if it's RGB or RGBA, otherwise copy them
+    pub fn from_image(image: &'a DynamicImage) -> Self {
+        match *image {
+            DynamicImage::ImageRgb8(ref rgb) => Self::from_rgb(rgb),
+            DynamicImage::ImageRgba8(ref rgba) => Self::from_rgba(rgba),
+            ref image => Frame::new(image)
+        }
+    }
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+    /// Return how many bytes each pixel has: 3 for RGB, or 4 for RGBA
+    pub fn channels(&self) -> usize { self.channels }
+    /// Return the raw pixels, row by row
+    pub fn as_raw(&self) -> &[u8] { &self.data }
+
+    /// Copy the pixels into an RgbaImage, for code that needs a `GenericImage`
+    pub fn to_rgba(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let i = (y as usize * self.width as usize + x as usize) * self.channels;
+            let alpha = if self.channels == 4 { self.data[i + 3] } else { 255 };
+            Rgba([self.data[i], self.data[i + 1], self.data[i + 2], alpha])
+        })
+    }
+
+    /// Return the color of a pixel, or None if it's transparent. See `alpha::visible`.
+    pub fn visible(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        let i = (y as usize * self.width as usize + x as usize) * self.channels;
+        let pixel = &self.data[i..i + self.channels];
+        if self.channels == 4 && pixel[3] < ALPHA_THRESHOLD {
+            None
+        } else {
+            Some([pixel[0], pixel[1], pixel[2]])
+        }
+    }
+}
+
+/// Call `f` for each row and collect the results in order.
+/// With the `rayon` feature, rows are done in parallel.
+pub fn map_rows<T, F>(rows: u32, f: F) -> Vec<T>
+    where T: Send,
+          F: Fn(u32) -> T + Send + Sync
+{
+    #[cfg(feature = "rayon")] {
+        (0..rows).into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "rayon"))] {
+        (0..rows).map(f).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgb, Rgba};
+
+    #[test]
+    fn test_frame() {
+        let rgba = RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 0, if x == 2 { 0 } else { 255 }]));
+        let rgb = RgbImage::from_fn(3, 2, |x, y| Rgb([x as u8, y as u8, 0]));
+
+        let (dynamic_rgba, dynamic_rgb) = (DynamicImage::ImageRgba8(rgba.clone()), DynamicImage::ImageRgb8(rgb.clone()));
+        let dynamic_luma = DynamicImage::ImageLuma8(dynamic_rgb.to_luma());
+
+        for frame in &[Frame::new(&rgba), Frame::from_rgba(&rgba), Frame::from_rgb(&rgb)] {
+            assert_eq!((frame.width(), frame.height()), (3, 2));
+            assert_eq!(frame.visible(1, 1), Some([1, 1, 0]));
+        }
+        assert_eq!(Frame::from_rgba(&rgba).to_rgba(), rgba);
+        assert_eq!(Frame::from_rgb(&rgb).to_rgba(), dynamic_rgb.to_rgba());
+
+        // RGB and RGBA images are borrowed, anything else is copied
+        if let DynamicImage::ImageRgba8(ref inner) = dynamic_rgba {
+            assert_eq!(Frame::from_image(&dynamic_rgba).as_raw().as_ptr(), inner.as_raw().as_ptr());
+        }
+        assert_eq!(Frame::from_image(&dynamic_rgb).channels(), 3);
+        assert_eq!(Frame::from_image(&dynamic_luma).channels(), 4);
+        assert_eq!(Frame::from_rgba(&rgba).visible(2, 0), None);
+        assert_eq!(Frame::from_rgb(&rgb).visible(2, 0), Some([2, 0, 0]));
+
+        assert_eq!(map_rows(4, |y| y * 2), vec![0, 2, 4, 6]);
+    }
+}
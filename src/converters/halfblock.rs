@@ -1,8 +1,48 @@
+use super::{
+    cells::{Cell, CellBuffer},
+    frame::{map_rows, Frame},
+    sgr::Color
+};
+
 use image::{GenericImage, Pixel};
 use std::io::{self, Write};
 
-#[derive(Clone, Copy, Debug)]
-pub struct HalfBlock;
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HalfBlock {
+    /// Shorten runs of the same color with REP, which not all terminals support
+    pub rep: bool
+}
+impl HalfBlock {
+    /// Convert an image to a grid of cells, each showing two pixels on top of each other
+    pub fn cells<I, P>(&self, image: &I) -> CellBuffer
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.frame_cells(&Frame::new(image))
+    }
+    /// Like `cells`, but reads the raw pixels directly
+    pub fn frame_cells(&self, frame: &Frame) -> CellBuffer {
+        let rows = map_rows(frame.height()/2, |y| {
+            (0..frame.width()).map(|x| {
+                let pixel = frame.visible(x, y*2);
+                let lower = if y*2+1 < frame.height() {
+                    frame.visible(x, y*2+1)
+                } else {
+                    Some([0, 0, 0])
+                };
+                let rgb = |pixel: [u8; 3]| Color::Rgb(pixel[0], pixel[1], pixel[2]);
+                match (pixel, lower) {
+                    (Some(pixel), Some(lower)) => Cell { glyph: '▀', fg: rgb(pixel), bg: rgb(lower), ..Cell::default() },
+                    // Leave the transparent half to the terminal's background
+                    (Some(pixel), None) => Cell { glyph: '▀', fg: rgb(pixel), ..Cell::default() },
+                    (None, Some(lower)) => Cell { glyph: '▄', fg: rgb(lower), ..Cell::default() },
+                    (None, None) => Cell::default()
+                }
+            }).collect()
+        });
+        CellBuffer::from_rows(frame.width(), rows)
+    }
+}
 
 impl super::Converter for HalfBlock {
     fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
@@ -10,28 +50,19 @@ impl super::Converter for HalfBlock {
               I: GenericImage<Pixel = P>,
               P: Pixel<Subpixel = u8>
     {
-        for y in 0..image.height()/2 {
-            if y > 0 {
-                write!(fmt, "\r\n")?;
-            }
-            let y = y*2;
-            for x in 0..image.width() {
-                let pixel = image.get_pixel(x, y).to_rgb().0;
-                let lower = if y+1 < image.height() {
-                    image.get_pixel(x, y+1).to_rgb().0
-                } else {
-                    [0, 0, 0]
-                };
-                write!(
-                    fmt,
-                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
-                    pixel[0], pixel[1], pixel[2],
-                    lower[0], lower[1], lower[2]
-                )?;
-            }
-            write!(fmt, "\x1b[0m")?;
-        }
-        Ok(())
+        self.cells(image).write_ansi(fmt, self.rep)
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        Some(self.cells(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.frame_cells(frame).write_ansi(fmt, self.rep)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        Some(self.frame_cells(frame))
     }
     fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
         (x, y * 2)
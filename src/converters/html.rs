@@ -0,0 +1,31 @@
+use super::{
+    cells::{CellLayout, HtmlStyle},
+    frame::Frame
+};
+
+use image::{GenericImage, Pixel};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Writes a HTML `<pre>` element instead of escape sequences,
+/// for pasting into web pages
+pub struct Html {
+    pub layout: CellLayout,
+    pub style: HtmlStyle
+}
+
+impl super::Converter for Html {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.display_frame(fmt, &Frame::new(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.layout.frame_cells(frame).write_html(fmt, self.style)
+    }
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        self.layout.actual_pos(x, y)
+    }
+}
@@ -0,0 +1,78 @@
+use super::{cells::CellBuffer, frame::Frame, DEFAULT_CELL_SIZE};
+
+use base64;
+use image::{png::PngEncoder, ColorType, GenericImage, ImageError, Pixel};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ITerm2 {
+    /// The size of a terminal cell in pixels
+    pub cell_size: (u32, u32)
+}
+impl Default for ITerm2 {
+    fn default() -> Self {
+        Self { cell_size: DEFAULT_CELL_SIZE }
+    }
+}
+
+impl super::Converter for ITerm2 {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.display_frame(fmt, &Frame::new(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        let color = if frame.channels() == 4 { ColorType::Rgba8 } else { ColorType::Rgb8 };
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .encode(frame.as_raw(), frame.width(), frame.height(), color)
+            .map_err(|err| match err {
+                ImageError::IoError(err) => err,
+                err => io::Error::other(err)
+            })?;
+
+        // Sizes without a unit are in cells, so this fills exactly what the image was sized for
+        write!(
+            fmt,
+            "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0:{}\x07",
+            png.len(),
+            frame.width().div_ceil(self.cell_size.0),
+            frame.height().div_ceil(self.cell_size.1),
+            base64::encode(&png)
+        )
+    }
+    fn frame_to_cells(&self, _frame: &Frame) -> Option<CellBuffer> {
+        None
+    }
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        (x * self.cell_size.0, y * self.cell_size.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converters::Converter;
+    use image::RgbImage;
+
+    #[test]
+    fn test_header() {
+        let image = RgbImage::new(25, 20);
+        let output = String::from_utf8(ITerm2::default().to_vec(&image)).unwrap();
+
+        let (header, data) = output.split_at(output.find(':').unwrap());
+        assert!(header.starts_with("\x1b]1337;File=inline=1;size="));
+        assert!(header.ends_with(";width=3;height=2;preserveAspectRatio=0"));
+        assert!(data.ends_with('\x07'));
+
+        // The size is of the PNG, before base64
+        let data = base64::decode(&data[1..data.len() - 1]).unwrap();
+        assert!(data.starts_with(b"\x89PNG"));
+        assert!(header.contains(&format!(";size={};", data.len())));
+
+        let output = String::from_utf8(ITerm2 { cell_size: (5, 20) }.to_vec(&image)).unwrap();
+        assert!(output.contains(";width=5;height=1;"));
+    }
+}
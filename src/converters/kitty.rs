@@ -0,0 +1,96 @@
+use super::{cells::CellBuffer, frame::Frame, DEFAULT_CELL_SIZE};
+
+use base64;
+use image::{GenericImage, Pixel};
+use std::io::{self, Write};
+
+/// The maximum amount of base64 data the kitty protocol accepts per escape code
+const CHUNK_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Kitty {
+    /// The image id to transmit. Sending another image with the same id
+    /// replaces the old one, so video frames don't stack on top of each other.
+    pub id: u32,
+    /// The size of a terminal cell in pixels
+    pub cell_size: (u32, u32)
+}
+impl Default for Kitty {
+    fn default() -> Self {
+        Self {
+            id: 1,
+            cell_size: DEFAULT_CELL_SIZE
+        }
+    }
+}
+
+impl super::Converter for Kitty {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.display_frame(fmt, &Frame::new(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        // Kitty reads RGB and RGBA alike, so the pixels are sent as they are
+        let data = base64::encode(frame.as_raw());
+
+        // Let the terminal scale the image to fill the cells it was sized for
+        let columns = frame.width().div_ceil(self.cell_size.0);
+        let rows = frame.height().div_ceil(self.cell_size.1);
+
+        let mut chunks = data.as_bytes().chunks(CHUNK_SIZE).peekable();
+        let mut first = true;
+        while let Some(chunk) = chunks.next() {
+            let more = if chunks.peek().is_some() { 1 } else { 0 };
+            if first {
+                write!(
+                    fmt,
+                    "\x1b_Ga=T,f={},s={},v={},c={},r={},i={},p=1,q=2,m={};",
+                    frame.channels() * 8, frame.width(), frame.height(), columns, rows, self.id, more
+                )?;
+                first = false;
+            } else {
+                write!(fmt, "\x1b_Gm={};", more)?;
+            }
+            fmt.write_all(chunk)?;
+            write!(fmt, "\x1b\\")?;
+        }
+        Ok(())
+    }
+    fn frame_to_cells(&self, _frame: &Frame) -> Option<CellBuffer> {
+        None
+    }
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        (x * self.cell_size.0, y * self.cell_size.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converters::Converter;
+    use image::RgbImage;
+
+    #[test]
+    fn test_chunking() {
+        // 40x30 RGBA is 4800 bytes, or 6400 bytes of base64
+        let image = RgbImage::new(40, 30);
+        let output = String::from_utf8(Kitty { id: 7, ..Kitty::default() }.to_vec(&image)).unwrap();
+
+        let chunks: Vec<&str> = output.split("\x1b\\").filter(|s| !s.is_empty()).collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].starts_with("\x1b_Ga=T,f=32,s=40,v=30,c=4,r=3,i=7,p=1,q=2,m=1;"));
+        assert!(chunks[1].starts_with("\x1b_Gm=0;"));
+        assert_eq!(chunks[0].split(';').nth(1).unwrap().len(), CHUNK_SIZE);
+        assert_eq!(chunks[1].split(';').nth(1).unwrap().len(), 6400 - CHUNK_SIZE);
+
+        // RGB frames are sent without an alpha channel, as 4800 bytes of base64
+        let mut output = Vec::new();
+        Kitty::default().display_frame(&mut output, &Frame::from_rgb(&image)).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("\x1b_Ga=T,f=24,s=40,v=30,"));
+        assert_eq!(output.split("\x1b\\").nth(1).unwrap().split(';').nth(1).unwrap().len(), 4800 - CHUNK_SIZE);
+    }
+}
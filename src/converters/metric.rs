@@ -0,0 +1,159 @@
+//! Ways to measure how different two colors look
+
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// A way to measure the difference between two colors
+pub enum ColorMetric {
+    /// Sum of the differences of each sRGB channel. Fast, but not very accurate.
+    #[default]
+    Manhattan,
+    /// Euclidean distance in CIELAB (ΔE*76)
+    Cie76,
+    /// The CIEDE2000 color difference (ΔE*00). The most accurate, but also the slowest.
+    Ciede2000,
+    /// Euclidean distance in OKLab
+    Oklab
+}
+impl ColorMetric {
+    /// Convert an sRGB color to the color space this metric works in
+    pub fn convert(self, (r, g, b): (u8, u8, u8)) -> [f64; 3] {
+        match self {
+            ColorMetric::Manhattan => [r as f64, g as f64, b as f64],
+            ColorMetric::Cie76 | ColorMetric::Ciede2000 => to_lab(r, g, b),
+            ColorMetric::Oklab => to_oklab(r, g, b)
+        }
+    }
+    /// Return the difference between two colors returned by `convert`
+    pub fn difference(self, a: [f64; 3], b: [f64; 3]) -> f64 {
+        match self {
+            ColorMetric::Manhattan => (a[0] - b[0]).abs() + (a[1] - b[1]).abs() + (a[2] - b[2]).abs(),
+            ColorMetric::Cie76 | ColorMetric::Oklab => {
+                ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+            },
+            ColorMetric::Ciede2000 => ciede2000(a, b)
+        }
+    }
+    /// Return the difference between two sRGB colors
+    pub fn distance(self, a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+        self.difference(self.convert(a), self.convert(b))
+    }
+}
+
+fn to_linear(c: u8) -> f64 {
+    let c = c as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+fn to_lab(r: u8, g: u8, b: u8) -> [f64; 3] {
+    let (r, g, b) = (to_linear(r), to_linear(g), to_linear(b));
+
+    // XYZ, relative to the D65 white point
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y =  0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+
+    let f = |t: f64| if t > 216.0 / 24389.0 {
+        t.cbrt()
+    } else {
+        (24389.0 / 27.0 * t + 16.0) / 116.0
+    };
+    let (x, y, z) = (f(x), f(y), f(z));
+
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+fn to_oklab(r: u8, g: u8, b: u8) -> [f64; 3] {
+    let (r, g, b) = (to_linear(r), to_linear(g), to_linear(b));
+
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s
+    ]
+}
+fn ciede2000(lab1: [f64; 3], lab2: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+
+    let c_mean = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let g = 0.5 * (1.0 - (c_mean.powi(7) / (c_mean.powi(7) + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+
+    let (c1, c2) = ((a1 * a1 + b1 * b1).sqrt(), (a2 * a2 + b2 * b2).sqrt());
+    let hue = |b: f64, a: f64| if a == 0.0 && b == 0.0 {
+        0.0
+    } else {
+        let h = b.atan2(a).to_degrees();
+        if h < 0.0 { h + 360.0 } else { h }
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 <= h1 {
+        h2 - h1 + 360.0
+    } else {
+        h2 - h1 - 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0
+        - 0.17 * (h_mean - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_mean).to_radians().cos()
+        + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_mean.powi(7) / (c_mean.powi(7) + 25f64.powi(7))).sqrt();
+    let r_t = -r_c * (2.0 * delta_theta * PI / 180.0).sin();
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ciede2000() {
+        // Test data from Sharma, Wu & Dalal's "The CIEDE2000 Color-Difference Formula"
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+            ([22.7233, 20.0904, -46.6940], [23.0331, 14.9730, -42.5619], 2.0373)
+        ];
+        for &(a, b, expected) in &pairs {
+            assert!((ciede2000(a, b) - expected).abs() < 0.0001, "{:?} {:?}", a, b);
+        }
+    }
+}
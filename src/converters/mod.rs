@@ -1,17 +1,62 @@
 //! The converters themselves
 
 #[cfg(feature = "sixel")] pub mod sixel;
+mod blocks;
+pub mod alpha;
+pub mod ansi16;
+pub mod ascii;
+pub mod braille;
+pub mod cells;
 pub mod color256;
+pub mod dither;
+pub mod frame;
 pub mod halfblock;
+pub mod html;
+pub mod iterm2;
+pub mod kitty;
+pub mod metric;
+pub mod quantizer;
+pub mod quadrant;
+pub mod registry;
+pub mod sextant;
+pub mod sgr;
+pub mod svg;
 pub mod truecolor;
 
 #[cfg(feature = "sixel")] pub use self::sixel::*;
+pub use self::alpha::*;
+pub use self::ansi16::*;
+pub use self::ascii::*;
+pub use self::braille::*;
+pub use self::cells::*;
 pub use self::color256::*;
+pub use self::dither::*;
+pub use self::frame::*;
 pub use self::halfblock::*;
+pub use self::html::*;
+pub use self::iterm2::*;
+pub use self::kitty::*;
+pub use self::metric::*;
+pub use self::quantizer::*;
+pub use self::quadrant::*;
+pub use self::registry::*;
+pub use self::sextant::*;
+pub use self::sgr::*;
+pub use self::svg::*;
 pub use self::truecolor::*;
 
+use capabilities::Capabilities;
+
 use image::{GenericImage, Pixel};
-use std::io::{self, Write};
+use std::{
+    io::{self, Write},
+    sync::Arc,
+    time::Duration
+};
+
+/// The size of a terminal cell in pixels that converters drawing real pixels assume,
+/// unless they're told the actual size. See `query::cell_size`.
+pub const DEFAULT_CELL_SIZE: (u32, u32) = (10, 10);
 
 /// A trait that converts an image to something displayable in the terminal
 pub trait Converter {
@@ -29,6 +74,23 @@ pub trait Converter {
         self.display(&mut buf, image).unwrap();
         buf
     }
+    /// Convert an image to a grid of cells, which makes it possible to only redraw what changed.
+    /// Returns None for converters that don't display text, like sixel.
+    fn to_cells<I, P>(&self, _image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        None
+    }
+    /// Like `display`, but reads raw pixels, so images that already are RGB or RGBA aren't copied.
+    /// All built-in converters override this, others get a copy of the pixels.
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.display(fmt, &frame.to_rgba())
+    }
+    /// Like `to_cells`, but reads raw pixels. See `display_frame`.
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        self.to_cells(&frame.to_rgba())
+    }
     /// Where is x/y in the terminal on the image?
     /// For example, TrueColor/Color256 would just return directly,
     /// because one character is one pixel.
@@ -37,14 +99,84 @@ pub trait Converter {
     }
 }
 
-#[derive(Clone, Copy)]
+/// An object safe version of `Converter`, for choosing converters at runtime,
+/// like from a `Registry`. All converters implement this automatically.
+/// `Arc<dyn DynConverter>` implements `Converter` in turn, so it works anywhere a converter does.
+pub trait DynConverter: Send + Sync {
+    /// Write raw pixels to specified io stream. See `Converter::display_frame`.
+    fn dyn_display(&self, fmt: &mut dyn Write, frame: &Frame) -> io::Result<()>;
+    /// Convert raw pixels to a grid of cells. See `Converter::frame_to_cells`.
+    fn dyn_to_cells(&self, frame: &Frame) -> Option<CellBuffer>;
+    /// Where is x/y in the terminal on the image? See `Converter::actual_pos`.
+    fn dyn_actual_pos(&self, x: u32, y: u32) -> (u32, u32);
+}
+impl<C: Converter + Send + Sync> DynConverter for C {
+    fn dyn_display(&self, mut fmt: &mut dyn Write, frame: &Frame) -> io::Result<()> {
+        self.display_frame(&mut fmt, frame)
+    }
+    fn dyn_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        self.frame_to_cells(frame)
+    }
+    fn dyn_actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        self.actual_pos(x, y)
+    }
+}
+impl Converter for Arc<dyn DynConverter> {
+    fn display<W, I, P>(&self, mut fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        (**self).dyn_display(&mut fmt, &Frame::new(image))
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        (**self).dyn_to_cells(&Frame::new(image))
+    }
+    fn display_frame<W: Write>(&self, mut fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        (**self).dyn_display(&mut fmt, frame)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        (**self).dyn_to_cells(frame)
+    }
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        (**self).dyn_actual_pos(x, y)
+    }
+}
+
+#[derive(Clone, Debug)]
 /// An enum with all built-in converter types,
 /// because trait objects don't work with generics.
 pub enum DynamicConverter {
-    #[cfg(feature = "sixel")] Sixel,
-    Color256,
-    HalfBlock,
-    TrueColor
+    #[cfg(feature = "sixel")] Sixel(Sixel),
+    Ansi16(Ansi16),
+    Ascii(Ascii),
+    Braille(Braille),
+    Color256(Color256),
+    HalfBlock(HalfBlock),
+    ITerm2(ITerm2),
+    Kitty(Kitty),
+    Quadrant,
+    Sextant,
+    TrueColor(TrueColor)
+}
+impl DynamicConverter {
+    /// Pick the best converter the terminal supports. See `Capabilities::detect`.
+    pub fn auto() -> Self {
+        Capabilities::detect(Duration::from_millis(200)).best_converter()
+    }
+    /// Tell converters that draw real pixels how big a terminal cell is.
+    /// Does nothing for converters that display text.
+    pub fn set_cell_size(&mut self, cell_size: (u32, u32)) {
+        match *self {
+            #[cfg(feature = "sixel")] DynamicConverter::Sixel(ref mut sixel) => sixel.cell_size = cell_size,
+            DynamicConverter::ITerm2(ref mut iterm2) => iterm2.cell_size = cell_size,
+            DynamicConverter::Kitty(ref mut kitty) => kitty.cell_size = cell_size,
+            _ => ()
+        }
+    }
 }
 impl Converter for DynamicConverter {
     fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
@@ -53,18 +185,93 @@ impl Converter for DynamicConverter {
               P: Pixel<Subpixel = u8>
     {
         match *self {
-            #[cfg(feature = "sixel")] DynamicConverter::Sixel => Sixel.display(fmt, image),
-            DynamicConverter::Color256 => Color256.display(fmt, image),
-            DynamicConverter::HalfBlock => HalfBlock.display(fmt, image),
-            DynamicConverter::TrueColor => TrueColor.display(fmt, image),
+            #[cfg(feature = "sixel")] DynamicConverter::Sixel(ref sixel) => sixel.display(fmt, image),
+            DynamicConverter::Ansi16(ref ansi16) => ansi16.display(fmt, image),
+            DynamicConverter::Ascii(ref ascii) => ascii.display(fmt, image),
+            DynamicConverter::Braille(ref braille) => braille.display(fmt, image),
+            DynamicConverter::Color256(ref color256) => color256.display(fmt, image),
+            DynamicConverter::HalfBlock(ref halfblock) => halfblock.display(fmt, image),
+            DynamicConverter::ITerm2(ref iterm2) => iterm2.display(fmt, image),
+            DynamicConverter::Kitty(ref kitty) => kitty.display(fmt, image),
+            DynamicConverter::Quadrant => Quadrant.display(fmt, image),
+            DynamicConverter::Sextant => Sextant.display(fmt, image),
+            DynamicConverter::TrueColor(ref truecolor) => truecolor.display(fmt, image),
+        }
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        match *self {
+            DynamicConverter::Ansi16(ref ansi16) => ansi16.to_cells(image),
+            DynamicConverter::Ascii(ref ascii) => ascii.to_cells(image),
+            DynamicConverter::Braille(ref braille) => braille.to_cells(image),
+            DynamicConverter::Color256(ref color256) => color256.to_cells(image),
+            DynamicConverter::HalfBlock(ref halfblock) => halfblock.to_cells(image),
+            DynamicConverter::Quadrant => Quadrant.to_cells(image),
+            DynamicConverter::Sextant => Sextant.to_cells(image),
+            DynamicConverter::TrueColor(ref truecolor) => truecolor.to_cells(image),
+            _ => None
+        }
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        match *self {
+            #[cfg(feature = "sixel")] DynamicConverter::Sixel(ref sixel) => sixel.display_frame(fmt, frame),
+            DynamicConverter::Ansi16(ref ansi16) => ansi16.display_frame(fmt, frame),
+            DynamicConverter::Ascii(ref ascii) => ascii.display_frame(fmt, frame),
+            DynamicConverter::Braille(ref braille) => braille.display_frame(fmt, frame),
+            DynamicConverter::Color256(ref color256) => color256.display_frame(fmt, frame),
+            DynamicConverter::HalfBlock(ref halfblock) => halfblock.display_frame(fmt, frame),
+            DynamicConverter::ITerm2(ref iterm2) => iterm2.display_frame(fmt, frame),
+            DynamicConverter::Kitty(ref kitty) => kitty.display_frame(fmt, frame),
+            DynamicConverter::Quadrant => Quadrant.display_frame(fmt, frame),
+            DynamicConverter::Sextant => Sextant.display_frame(fmt, frame),
+            DynamicConverter::TrueColor(ref truecolor) => truecolor.display_frame(fmt, frame)
+        }
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        match *self {
+            DynamicConverter::Ansi16(ref ansi16) => ansi16.frame_to_cells(frame),
+            DynamicConverter::Ascii(ref ascii) => ascii.frame_to_cells(frame),
+            DynamicConverter::Braille(ref braille) => braille.frame_to_cells(frame),
+            DynamicConverter::Color256(ref color256) => color256.frame_to_cells(frame),
+            DynamicConverter::HalfBlock(ref halfblock) => halfblock.frame_to_cells(frame),
+            DynamicConverter::Quadrant => Quadrant.frame_to_cells(frame),
+            DynamicConverter::Sextant => Sextant.frame_to_cells(frame),
+            DynamicConverter::TrueColor(ref truecolor) => truecolor.frame_to_cells(frame),
+            _ => None
         }
     }
     fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
         match *self {
-            #[cfg(feature = "sixel")] DynamicConverter::Sixel => Sixel.actual_pos(x, y),
-            DynamicConverter::Color256 => Color256.actual_pos(x, y),
-            DynamicConverter::HalfBlock => HalfBlock.actual_pos(x, y),
-            DynamicConverter::TrueColor => TrueColor.actual_pos(x, y)
+            #[cfg(feature = "sixel")] DynamicConverter::Sixel(ref sixel) => sixel.actual_pos(x, y),
+            DynamicConverter::Ansi16(ref ansi16) => ansi16.actual_pos(x, y),
+            DynamicConverter::Ascii(ref ascii) => ascii.actual_pos(x, y),
+            DynamicConverter::Braille(ref braille) => braille.actual_pos(x, y),
+            DynamicConverter::Color256(ref color256) => color256.actual_pos(x, y),
+            DynamicConverter::HalfBlock(ref halfblock) => halfblock.actual_pos(x, y),
+            DynamicConverter::ITerm2(ref iterm2) => iterm2.actual_pos(x, y),
+            DynamicConverter::Kitty(ref kitty) => kitty.actual_pos(x, y),
+            DynamicConverter::Quadrant => Quadrant.actual_pos(x, y),
+            DynamicConverter::Sextant => Sextant.actual_pos(x, y),
+            DynamicConverter::TrueColor(ref truecolor) => truecolor.actual_pos(x, y)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn test_dynamic_to_cells() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let halfblock = DynamicConverter::HalfBlock(HalfBlock::default());
+        assert_eq!(halfblock.to_cells(&image), HalfBlock::default().to_cells(&image));
+        assert!(halfblock.to_cells(&image).is_some());
+        assert_eq!(halfblock.frame_to_cells(&Frame::from_rgba(&image)), halfblock.to_cells(&image));
+        assert_eq!(DynamicConverter::Kitty(Kitty::default()).to_cells(&image), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! Fast lookups of the closest color in a palette
+
+use super::{color256::COLORS, ColorMetric};
+
+use std::sync::{
+    atomic::{AtomicU16, Ordering},
+    OnceLock
+};
+
+/// How many bits of each channel are used to index the table
+const BITS: u32 = 6;
+const SIZE: usize = 1 << (BITS * 3);
+
+/// Maps colors to the closest entry of a palette.
+/// Results are cached in a table with 6 bits of precision per channel,
+/// so each bucket only needs to be compared against the palette once.
+///
+/// This makes lookups approximate: every color in a bucket gets the color closest to
+/// one color in it. With the xterm palette and the Manhattan metric, about 2% of colors
+/// get a different index than `lookup_color` would give them, but never one more than
+/// 18 further away, since no channel is off by more than 3. Use `closest` for exact results.
+#[derive(Debug)]
+pub struct Quantizer {
+    palette: Vec<(u8, u8, u8)>,
+    converted: Vec<[f64; 3]>,
+    metric: ColorMetric,
+    /// The palette index plus one, or zero if it hasn't been computed yet
+    table: Vec<AtomicU16>
+}
+impl Quantizer {
+    /// Create a new quantizer for the palette, which can have at most 256 colors
+    pub fn new(palette: &[(u8, u8, u8)], metric: ColorMetric) -> Self {
+        assert!(!palette.is_empty() && palette.len() <= 256, "palette must have between 1 and 256 colors");
+        Self {
+            palette: palette.to_vec(),
+            converted: palette.iter().map(|&color| metric.convert(color)).collect(),
+            metric,
+            table: (0..SIZE).map(|_| AtomicU16::new(0)).collect()
+        }
+    }
+    /// Return a shared quantizer for the xterm 256 color palette
+    pub fn xterm(metric: ColorMetric) -> &'static Self {
+        static QUANTIZERS: [OnceLock<Quantizer>; 4] = [OnceLock::new(), OnceLock::new(), OnceLock::new(), OnceLock::new()];
+        QUANTIZERS[metric_index(metric)].get_or_init(|| Self::new(COLORS, metric))
+    }
+    /// Return a shared quantizer for the first 16 colors of the xterm palette
+    pub fn xterm16(metric: ColorMetric) -> &'static Self {
+        static QUANTIZERS: [OnceLock<Quantizer>; 4] = [OnceLock::new(), OnceLock::new(), OnceLock::new(), OnceLock::new()];
+        QUANTIZERS[metric_index(metric)].get_or_init(|| Self::new(&COLORS[..16], metric))
+    }
+    /// The palette this quantizer maps to
+    pub fn palette(&self) -> &[(u8, u8, u8)] {
+        &self.palette
+    }
+    /// The metric used to compare colors
+    pub fn metric(&self) -> ColorMetric {
+        self.metric
+    }
+    /// Return the index of the palette color closest to this color, approximately.
+    /// See `Quantizer`.
+    pub fn lookup(&self, r: u8, g: u8, b: u8) -> u8 {
+        let shift = 8 - BITS;
+        let index = ((r as usize >> shift) << (BITS * 2)) | ((g as usize >> shift) << BITS) | (b as usize >> shift);
+
+        let cached = self.table[index].load(Ordering::Relaxed);
+        if cached != 0 {
+            return (cached - 1) as u8;
+        }
+
+        // Replicate the high bits to pick a color within the bucket,
+        // so that black and white are still mapped exactly
+        let bucket = |c: u8| (c >> shift << shift) | (c >> BITS);
+        let closest = self.closest(bucket(r), bucket(g), bucket(b));
+        self.table[index].store(closest as u16 + 1, Ordering::Relaxed);
+        closest
+    }
+    /// Find the closest color without using the table
+    pub fn closest(&self, r: u8, g: u8, b: u8) -> u8 {
+        let color = self.metric.convert((r, g, b));
+        let mut min: (u8, f64) = (0, f64::INFINITY);
+
+        for (i, &other) in self.converted.iter().enumerate() {
+            let diff = self.metric.difference(color, other);
+            if diff < min.1 {
+                min = (i as u8, diff);
+            }
+        }
+
+        min.0
+    }
+}
+/// Return where the shared quantizers for a metric are stored
+fn metric_index(metric: ColorMetric) -> usize {
+    match metric {
+        ColorMetric::Manhattan => 0,
+        ColorMetric::Cie76 => 1,
+        ColorMetric::Ciede2000 => 2,
+        ColorMetric::Oklab => 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use converters::color256::lookup_color;
+
+    #[test]
+    fn test_lookup() {
+        let quantizer = Quantizer::new(&[(0, 0, 0), (255, 255, 255), (255, 0, 0)], ColorMetric::Manhattan);
+        assert_eq!(quantizer.lookup(10, 20, 30), 0);
+        assert_eq!(quantizer.lookup(250, 240, 230), 1);
+        assert_eq!(quantizer.lookup(200, 30, 10), 2);
+        // Second time is from the table
+        assert_eq!(quantizer.lookup(200, 30, 10), 2);
+    }
+    #[test]
+    fn test_xterm() {
+        for &metric in &[ColorMetric::Manhattan, ColorMetric::Oklab] {
+            let quantizer = Quantizer::xterm(metric);
+            for &(r, g, b) in &[(0, 0, 0), (95, 135, 175), (215, 175, 135), (238, 238, 238)] {
+                assert_eq!(quantizer.lookup(r, g, b), quantizer.closest(r, g, b));
+            }
+        }
+    }
+    #[test]
+    fn test_approximation() {
+        let distance = |(r, g, b): (u8, u8, u8), i: u8| {
+            let (r2, g2, b2) = COLORS[i as usize];
+            (r as i32 - r2 as i32).abs() + (g as i32 - g2 as i32).abs() + (b as i32 - b2 as i32).abs()
+        };
+        let quantizer = Quantizer::xterm(ColorMetric::Manhattan);
+        let (mut total, mut different) = (0, 0);
+        for r in (0..=255).step_by(7) {
+            for g in (0..=255).step_by(7) {
+                for b in (0..=255).step_by(7) {
+                    let exact = lookup_color(r, g, b);
+                    let approximate = quantizer.lookup(r, g, b);
+                    let error = distance((r, g, b), approximate) - distance((r, g, b), exact);
+                    assert!(error <= 18, "{:?} is {} further away", (r, g, b), error);
+
+                    total += 1;
+                    if approximate != exact {
+                        different += 1;
+                    }
+                }
+            }
+        }
+        assert!(different * 100 < total * 3, "{} of {} colors are different", different, total);
+    }
+}
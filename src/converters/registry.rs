@@ -0,0 +1,186 @@
+//! Converters that can be picked by name at runtime
+
+use super::*;
+
+use std::{fmt, sync::Arc, time::Duration};
+
+/// The name that picks the best converter the terminal supports
+const AUTO: &str = "auto";
+
+#[derive(Clone, Debug, Default)]
+/// Settings that a `Registry` passes on to the converters it creates.
+/// Converters only use what applies to them.
+pub struct ConverterOptions {
+    pub metric: ColorMetric,
+    pub dither: Dither,
+    /// Shorten runs of the same color with REP
+    pub rep: bool,
+    /// The 16 terminal colors, if they're known
+    pub palette: Option<[(u8, u8, u8); 16]>,
+    /// A quantizer for the terminal's 256 colors, if they're known
+    pub quantizer: Option<Arc<Quantizer>>,
+    /// How to style HTML output
+    pub html_style: HtmlStyle,
+    /// The size of a terminal cell in pixels, if it's known
+    pub cell_size: Option<(u32, u32)>
+}
+
+/// Creates a converter from the options
+pub type Factory = Box<dyn Fn(&ConverterOptions) -> Arc<dyn DynConverter> + Send + Sync>;
+
+#[derive(Default)]
+/// A list of named converters.
+/// Downstream crates can register their own converters next to the built-in ones.
+pub struct Registry {
+    entries: Vec<(String, Factory)>,
+    /// Whether "auto" picks one of the other converters
+    auto: bool
+}
+impl Registry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a registry with all built-in converters, plus "auto" which picks the best one
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.auto = true;
+        registry.register("ansi16", |options| {
+            let ansi16 = match options.palette {
+                Some(palette) => Ansi16::with_palette(palette, options.metric),
+                None => Ansi16 { metric: options.metric, ..Ansi16::default() }
+            };
+            Arc::new(Ansi16 { dither: options.dither, ..ansi16 })
+        });
+        registry.register("ascii", |_| Arc::new(Ascii::default()));
+        registry.register("braille", |_| Arc::new(Braille::default()));
+        registry.register("color256", |options| Arc::new(Color256 {
+            metric: options.metric,
+            dither: options.dither,
+            quantizer: options.quantizer.clone(),
+            rep: options.rep
+        }));
+        registry.register("halfblock", |options| Arc::new(HalfBlock { rep: options.rep }));
+        registry.register("html", |options| Arc::new(Html { style: options.html_style, ..Html::default() }));
+        registry.register("iterm2", |options| Arc::new(ITerm2 {
+            cell_size: options.cell_size.unwrap_or(DEFAULT_CELL_SIZE)
+        }));
+        registry.register("kitty", |options| Arc::new(Kitty {
+            cell_size: options.cell_size.unwrap_or(DEFAULT_CELL_SIZE),
+            ..Kitty::default()
+        }));
+        registry.register("quadrant", |_| Arc::new(Quadrant));
+        registry.register("sextant", |_| Arc::new(Sextant));
+        #[cfg(feature = "sixel")]
+        registry.register("sixel", |options| Arc::new(Sixel {
+            dither: options.dither,
+            cell_size: options.cell_size.unwrap_or(DEFAULT_CELL_SIZE),
+            ..Sixel::default()
+        }));
+        registry.register("svg", |_| Arc::new(Svg::default()));
+        registry.register("truecolor", |options| Arc::new(TrueColor { rep: options.rep }));
+        registry
+    }
+    /// Add a converter, replacing any other with the same name
+    pub fn register<F>(&mut self, name: &str, factory: F)
+        where F: Fn(&ConverterOptions) -> Arc<dyn DynConverter> + Send + Sync + 'static
+    {
+        if name == AUTO {
+            self.auto = false;
+        }
+        let factory: Factory = Box::new(factory);
+        match self.entries.iter_mut().find(|entry| entry.0 == name) {
+            Some(entry) => entry.1 = factory,
+            None => self.entries.push((name.to_string(), factory))
+        }
+    }
+    /// Return the names of all converters, in the order they were registered
+    pub fn names(&self) -> Vec<&str> {
+        let auto = if self.auto { Some(AUTO) } else { None };
+        auto.into_iter().chain(self.entries.iter().map(|entry| &*entry.0)).collect()
+    }
+    /// Create the converter with this name, or None if there is none.
+    /// "auto" asks the terminal which converter is best, see `Capabilities::detect`,
+    /// and then creates that one with the same options.
+    pub fn create(&self, name: &str, options: &ConverterOptions) -> Option<Arc<dyn DynConverter>> {
+        if self.auto && name == AUTO {
+            self.create_with(name, options, &Capabilities::detect(Duration::from_millis(200)))
+        } else {
+            self.create_with(name, options, &Capabilities::default())
+        }
+    }
+    /// Like `create`, but "auto" picks the best converter for `capabilities`
+    /// instead of asking the terminal
+    pub fn create_with(
+        &self,
+        name: &str,
+        options: &ConverterOptions,
+        capabilities: &Capabilities
+    ) -> Option<Arc<dyn DynConverter>> {
+        let name = if self.auto && name == AUTO { capabilities.best_name() } else { name };
+        self.entries.iter()
+            .find(|entry| entry.0 == name)
+            .map(|entry| (entry.1)(options))
+    }
+}
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("names", &self.names())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgba, RgbaImage};
+    use std::io::{self, Write};
+
+    struct Hash;
+    impl Converter for Hash {
+        fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+            where W: Write,
+                  I: GenericImage<Pixel = P>,
+                  P: Pixel<Subpixel = u8>
+        {
+            for _ in 0..image.width() {
+                write!(fmt, "#")?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::builtin();
+        assert!(registry.names().contains(&"halfblock"));
+        assert!(registry.create("hash", &ConverterOptions::default()).is_none());
+
+        registry.register("hash", |_| Arc::new(Hash));
+        assert_eq!(registry.names().last(), Some(&"hash"));
+
+        let image = RgbaImage::from_pixel(3, 1, Rgba([255, 0, 0, 255]));
+        let hash = registry.create("hash", &ConverterOptions::default()).unwrap();
+        assert_eq!(hash.to_vec(&image), b"###");
+        assert_eq!(hash.to_cells(&image), None);
+
+        let options = ConverterOptions { rep: true, ..ConverterOptions::default() };
+        let truecolor = registry.create("truecolor", &options).unwrap();
+        assert_eq!(truecolor.to_vec(&image), TrueColor { rep: true }.to_vec(&image));
+        assert_eq!(truecolor.to_cells(&image), TrueColor::default().to_cells(&image));
+
+        // Auto creates the best converter through its own factory, with the same options
+        let truecolor_caps = Capabilities::from_vars(|name| {
+            if name == "COLORTERM" { Some("truecolor".to_string()) } else { None }
+        });
+        assert_eq!(registry.names()[0], "auto");
+        let auto = registry.create_with("auto", &options, &truecolor_caps).unwrap();
+        assert_eq!(auto.to_vec(&image), HalfBlock { rep: true }.to_vec(&image));
+
+        registry.register("auto", |_| Arc::new(Hash));
+        assert_eq!(registry.names().iter().filter(|&&name| name == "auto").count(), 1);
+        assert_eq!(registry.create_with("auto", &options, &truecolor_caps).unwrap().to_vec(&image), b"###");
+    }
+}
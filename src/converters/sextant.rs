@@ -0,0 +1,62 @@
+use super::{cells::CellBuffer, frame::Frame};
+
+use image::{GenericImage, Pixel};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sextant;
+
+impl Sextant {
+    /// Convert raw pixels to a grid of cells
+    pub fn frame_cells(&self, frame: &Frame) -> CellBuffer {
+        super::blocks::cells(frame, 2, 3, super::blocks::sextant)
+    }
+}
+
+impl super::Converter for Sextant {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.frame_cells(&Frame::new(image)).write_ansi(fmt, false)
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        Some(self.frame_cells(&Frame::new(image)))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.frame_cells(frame).write_ansi(fmt, false)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        Some(self.frame_cells(frame))
+    }
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        (x * 2, y * 3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use converters::{sgr::Color, Converter};
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_sextant() {
+        let image = RgbImage::from_fn(2, 3, |_, y| match y {
+            0 => Rgb([255, 0, 0]),
+            _ => Rgb([0, 0, 255])
+        });
+        let cells = Sextant.to_cells(&image).unwrap();
+        let cell = cells.get(0, 0);
+        assert_eq!(cell.glyph, '\u{1FB02}');
+        assert_eq!(cell.fg, Color::Rgb(255, 0, 0));
+        assert_eq!(cell.bg, Color::Rgb(0, 0, 255));
+
+        assert_eq!(Sextant.actual_pos(3, 4), (6, 12));
+    }
+}
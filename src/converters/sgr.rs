@@ -0,0 +1,301 @@
+//! Writing colored characters without repeating escape sequences the terminal already knows
+
+use super::{
+    cells::{Attrs, Cell},
+    color256::COLORS
+};
+
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A color as the terminal knows it
+pub enum Color {
+    /// The terminal's own foreground or background
+    Default,
+    /// A color from the 256 color palette
+    Indexed(u8),
+    Rgb(u8, u8, u8)
+}
+impl Color {
+    /// Return the RGB value of this color, assuming the xterm palette.
+    /// Returns None for the terminal's default, which is unknown.
+    pub fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Default => None,
+            Color::Indexed(i) => Some(COLORS[i as usize]),
+            Color::Rgb(r, g, b) => Some((r, g, b))
+        }
+    }
+}
+
+/// Writes characters one cell at a time, but only sends SGR sequences when
+/// the color or attributes actually change. Optionally shortens runs of the same character
+/// with REP (CSI b), which most terminals support but not all.
+pub struct SgrWriter<'a, W: Write + 'a> {
+    fmt: &'a mut W,
+    rep: bool,
+    /// The colors the terminal is currently using, or None if unknown
+    fg: Option<Color>,
+    bg: Option<Color>,
+    attrs: Attrs,
+    /// The last character written, and how many times it still needs repeating
+    last: Option<(char, u32)>
+}
+impl<'a, W: Write + 'a> SgrWriter<'a, W> {
+    /// Create a new writer. Makes no assumptions about the terminal's current colors,
+    /// but assumes no attributes are set.
+    pub fn new(fmt: &'a mut W, rep: bool) -> Self {
+        Self {
+            fmt,
+            rep,
+            fg: None,
+            bg: None,
+            attrs: Attrs::default(),
+            last: None
+        }
+    }
+    /// Assume the terminal's colors and attributes were just reset,
+    /// like at the end of the line before
+    pub fn assume_reset(&mut self) {
+        self.fg = Some(Color::Default);
+        self.bg = Some(Color::Default);
+        self.attrs = Attrs::default();
+    }
+    /// Write a single cell.
+    /// The foreground of a blank cell can't be seen, so it's left as is.
+    pub fn cell(&mut self, cell: &Cell) -> io::Result<()> {
+        let glyph = cell.glyph;
+        let fg = if cell.is_blank() { None } else { Some(cell.fg) }.filter(|&fg| self.fg != Some(fg));
+        let bg = Some(cell.bg).filter(|&bg| self.bg != Some(bg));
+        let attrs = Some(cell.attrs).filter(|&attrs| self.attrs != attrs);
+
+        if fg.is_none() && bg.is_none() && attrs.is_none() {
+            if let Some((last, ref mut count)) = self.last {
+                if last == glyph && self.rep {
+                    *count += 1;
+                    return Ok(());
+                }
+            }
+        }
+        self.flush_rep()?;
+
+        let mut params = Vec::new();
+        if let Some(attrs) = attrs {
+            let old = self.attrs;
+            for &(on, was, set, unset) in &[
+                (attrs.bold, old.bold, 1, 22),
+                (attrs.italic, old.italic, 3, 23),
+                (attrs.underline, old.underline, 4, 24),
+                (attrs.reverse, old.reverse, 7, 27)
+            ] {
+                if on != was {
+                    separate(&mut params);
+                    write!(params, "{}", if on { set } else { unset })?;
+                }
+            }
+            self.attrs = attrs;
+        }
+        if let Some(fg) = fg {
+            separate(&mut params);
+            write_color(&mut params, fg, 30)?;
+            self.fg = Some(fg);
+        }
+        if let Some(bg) = bg {
+            separate(&mut params);
+            write_color(&mut params, bg, 40)?;
+            self.bg = Some(bg);
+        }
+        if !params.is_empty() {
+            self.fmt.write_all(b"\x1b[")?;
+            self.fmt.write_all(&params)?;
+            self.fmt.write_all(b"m")?;
+        }
+        write!(self.fmt, "{}", glyph)?;
+        self.last = Some((glyph, 0));
+        Ok(())
+    }
+    /// Reset all colors and attributes to the terminal's default
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.flush_rep()?;
+        self.last = None;
+        self.assume_reset();
+        write!(self.fmt, "\x1b[0m")
+    }
+    /// Move the cursor to x and y, counting from 1
+    pub fn goto(&mut self, x: u32, y: u32) -> io::Result<()> {
+        self.flush_rep()?;
+        self.last = None;
+        write!(self.fmt, "\x1b[{};{}H", y, x)
+    }
+    /// Move to the start of the next line
+    pub fn newline(&mut self) -> io::Result<()> {
+        self.flush_rep()?;
+        self.last = None;
+        write!(self.fmt, "\r\n")
+    }
+    /// Write any characters that are still waiting to be repeated
+    pub fn flush_rep(&mut self) -> io::Result<()> {
+        if let Some((glyph, ref mut count)) = self.last {
+            if *count > 0 {
+                let rep = format!("\x1b[{}b", count);
+                if rep.len() < glyph.len_utf8() * *count as usize {
+                    write!(self.fmt, "{}", rep)?;
+                } else {
+                    for _ in 0..*count {
+                        write!(self.fmt, "{}", glyph)?;
+                    }
+                }
+                *count = 0;
+            }
+        }
+        Ok(())
+    }
+}
+/// Add a separator if there are SGR parameters before this one
+fn separate(params: &mut Vec<u8>) {
+    if !params.is_empty() {
+        params.push(b';');
+    }
+}
+/// Write the SGR parameters for a color, where `base` is 30 for foreground and 40 for background
+fn write_color<W: Write>(fmt: &mut W, color: Color, base: u8) -> io::Result<()> {
+    match color {
+        Color::Default => write!(fmt, "{}", base + 9),
+        // The 16 standard colors have their own, shorter and more widely supported codes
+        Color::Indexed(i) if i < 8 => write!(fmt, "{}", base + i),
+        Color::Indexed(i) if i < 16 => write!(fmt, "{}", base + 60 + i - 8),
+        Color::Indexed(i) => write!(fmt, "{};5;{}", base + 8, i),
+        Color::Rgb(r, g, b) => write!(fmt, "{};2;{};{};{}", base + 8, r, g, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converters::*;
+
+    use image::{Rgba, RgbaImage};
+
+    /// Read the output of a converter back, like a terminal would.
+    /// Only understands what the text converters use.
+    fn render(output: &[u8]) -> Vec<Vec<(char, Color, Color)>> {
+        let output = String::from_utf8(output.to_vec()).unwrap();
+        let mut chars = output.chars().peekable();
+        let mut lines = vec![Vec::new()];
+        let (mut fg, mut bg) = (Color::Default, Color::Default);
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' => (),
+                '\n' => lines.push(Vec::new()),
+                '\x1b' => {
+                    assert_eq!(chars.next(), Some('['));
+                    let mut params = String::new();
+                    while chars.peek().map(|c| c.is_ascii_digit() || *c == ';').unwrap_or(false) {
+                        params.push(chars.next().unwrap());
+                    }
+                    let mut params = params.split(';').map(|param| param.parse::<u32>().unwrap_or(0));
+                    match chars.next() {
+                        Some('m') => while let Some(param) = params.next() {
+                            let mut color = || match params.next() {
+                                Some(5) => Color::Indexed(params.next().unwrap() as u8),
+                                Some(2) => {
+                                    let mut channel = || params.next().unwrap() as u8;
+                                    Color::Rgb(channel(), channel(), channel())
+                                },
+                                other => panic!("unknown color type {:?}", other)
+                            };
+                            match param {
+                                0 => { fg = Color::Default; bg = Color::Default; },
+                                30..=37 => fg = Color::Indexed((param - 30) as u8),
+                                40..=47 => bg = Color::Indexed((param - 40) as u8),
+                                90..=97 => fg = Color::Indexed((param - 90 + 8) as u8),
+                                100..=107 => bg = Color::Indexed((param - 100 + 8) as u8),
+                                38 => fg = color(),
+                                39 => fg = Color::Default,
+                                48 => bg = color(),
+                                49 => bg = Color::Default,
+                                other => panic!("unknown SGR {}", other)
+                            }
+                        },
+                        Some('b') => {
+                            let line = lines.last_mut().unwrap();
+                            let &(glyph, _, _) = line.last().expect("nothing to repeat");
+                            for _ in 0..params.next().unwrap() {
+                                line.push((glyph, fg, bg));
+                            }
+                        },
+                        other => panic!("unknown CSI {:?}", other)
+                    }
+                },
+                c => lines.last_mut().unwrap().push((c, fg, bg))
+            }
+        }
+        // The foreground of a blank doesn't matter
+        for cell in lines.iter_mut().flat_map(|line| line.iter_mut()) {
+            if cell.0 == ' ' {
+                cell.1 = Color::Default;
+            }
+        }
+        lines
+    }
+    fn image() -> RgbaImage {
+        RgbaImage::from_fn(24, 6, |x, y| match (x, y) {
+            (3, 1) => Rgba([0, 0, 0, 0]),
+            (_, 0) | (_, 1) => Rgba([255, 0, 0, 255]),
+            (x, _) if x < 12 => Rgba([0, 0, 255, 255]),
+            (x, y) => Rgba([(x * 10) as u8, (y * 40) as u8, 0, 255])
+        })
+    }
+    fn check<C: Converter>(make: fn(bool) -> C, expected: &[Vec<(char, Color, Color)>]) {
+        let plain = make(false).to_vec(&image());
+        let rep = make(true).to_vec(&image());
+        assert_eq!(render(&plain), expected);
+        assert_eq!(render(&rep), expected);
+        assert!(rep.len() < plain.len());
+    }
+
+    #[test]
+    fn test_truecolor() {
+        let image = image();
+        let expected: Vec<Vec<_>> = image.rows()
+            .map(|row| row.map(|pixel| match pixel[3] {
+                0 => (' ', Color::Default, Color::Default),
+                _ => (' ', Color::Default, Color::Rgb(pixel[0], pixel[1], pixel[2]))
+            }).collect())
+            .collect();
+        check(|rep| TrueColor { rep }, &expected);
+
+        let plain = TrueColor { rep: false }.to_vec(&image);
+        assert!(plain.len() < 8 * image.width() as usize * image.height() as usize);
+    }
+    #[test]
+    fn test_halfblock() {
+        let image = image();
+        let color = |x, y| match *image.get_pixel(x, y) {
+            Rgba([_, _, _, 0]) => None,
+            Rgba([r, g, b, _]) => Some(Color::Rgb(r, g, b))
+        };
+        let expected: Vec<Vec<_>> = (0..image.height() / 2)
+            .map(|y| (0..image.width()).map(|x| match (color(x, y * 2), color(x, y * 2 + 1)) {
+                (Some(top), Some(bottom)) => ('▀', top, bottom),
+                (Some(top), None) => ('▀', top, Color::Default),
+                (None, Some(bottom)) => ('▄', bottom, Color::Default),
+                (None, None) => (' ', Color::Default, Color::Default)
+            }).collect())
+            .collect();
+        check(|rep| HalfBlock { rep }, &expected);
+    }
+    #[test]
+    fn test_color256() {
+        let image = image();
+        let quantizer = Quantizer::xterm(ColorMetric::default());
+        let expected: Vec<Vec<_>> = image.rows()
+            .map(|row| row.map(|pixel| match pixel[3] {
+                0 => (' ', Color::Default, Color::Default),
+                _ => (' ', Color::Default, Color::Indexed(quantizer.lookup(pixel[0], pixel[1], pixel[2])))
+            }).collect())
+            .collect();
+        check(|rep| Color256 { rep, ..Color256::default() }, &expected);
+    }
+}
@@ -1,28 +1,28 @@
+use super::{cells::CellBuffer, frame::Frame, Dither, DEFAULT_CELL_SIZE};
+
 use image::{GenericImage, Pixel};
 use std::{
-    io::{self, Write},
-    os::raw::{c_uchar, c_int, c_void},
-    ptr,
-    slice
+    collections::HashMap,
+    io::{self, Write}
 };
 
-type Alloc = *mut c_void;
-type Dither = *mut c_void;
-type Output = *mut c_void;
-type Status = c_int;
-type WriteFn = extern "C" fn(data: *mut c_uchar, len: c_int, userdata: *mut c_void) -> Status;
-
-const SIXEL_BUILTIN_XTERM256: c_int = 3;
-
-#[link(name = "sixel")]
-extern "C" {
-    fn sixel_output_new(output: *mut Output, write_fn: WriteFn, userdata: *mut c_void, alloc: Alloc) -> Status;
-    fn sixel_dither_get(kind: c_int) -> Dither;
-    fn sixel_encode(data: *mut c_uchar, width: c_int, height: c_int, _: c_int, dither: Dither, output: Output) -> Status;
-}
-
 #[derive(Clone, Copy, Debug)]
-pub struct Sixel;
+pub struct Sixel {
+    /// The maximum number of colors in the adaptive palette, between 1 and 256
+    pub colors: u16,
+    pub dither: Dither,
+    /// The size of a terminal cell in pixels
+    pub cell_size: (u32, u32)
+}
+impl Default for Sixel {
+    fn default() -> Self {
+        Self {
+            colors: 256,
+            dither: Dither::None,
+            cell_size: DEFAULT_CELL_SIZE
+        }
+    }
+}
 
 impl super::Converter for Sixel {
     fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
@@ -30,53 +30,246 @@ impl super::Converter for Sixel {
               I: GenericImage<Pixel = P>,
               P: Pixel<Subpixel = u8>
     {
-        extern "C" fn write_fn(data: *mut c_uchar, len: c_int, userdata: *mut c_void) -> c_int {
-            unsafe {
-                let output: &mut Vec<u8> = &mut *(userdata as *mut Vec<u8>);
-                output.write_all(slice::from_raw_parts(data, len as usize)).unwrap();
-                0
+        self.display_frame(fmt, &Frame::new(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut opaque = Vec::with_capacity(width * height);
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                let pixel = frame.visible(x, y);
+                pixels.push(pixel.unwrap_or([0, 0, 0]));
+                opaque.push(pixel.is_some());
             }
         }
 
-        let mut data = Vec::with_capacity(image.width() as usize * image.height() as usize * 3);
-        for y in 0..image.height() {
-            for x in 0..image.width() {
-                let pixel = image.get_pixel(x, y).to_rgb();
-                data.push(pixel[0]);
-                data.push(pixel[1]);
-                data.push(pixel[2]);
+        let max = self.colors.clamp(1, 256) as usize;
+        let visible: Vec<[u8; 3]> = pixels.iter()
+            .zip(&opaque)
+            .filter(|&(_, &opaque)| opaque)
+            .map(|(&pixel, _)| pixel)
+            .collect();
+        let mut palette = quantize(&visible, max);
+        if palette.is_empty() {
+            palette.push([0, 0, 0]);
+        }
+        let tuples: Vec<(u8, u8, u8)> = palette.iter().map(|c| (c[0], c[1], c[2])).collect();
+        let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+        let indices = self.dither.apply(&pixels, width, &tuples, |r, g, b| {
+            *cache.entry([r, g, b]).or_insert_with(|| nearest([r, g, b], &palette))
+        });
+        let indices: Vec<Option<u8>> = indices.into_iter()
+            .zip(opaque)
+            .map(|(index, opaque)| if opaque { Some(index) } else { None })
+            .collect();
+
+        encode(fmt, width, height, &palette, &indices)
+    }
+    fn frame_to_cells(&self, _frame: &Frame) -> Option<CellBuffer> {
+        None
+    }
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        (x * self.cell_size.0, y * self.cell_size.1)
+    }
+}
+
+/// Create a palette of at most `max` colors using median cut.
+/// If the image has few enough unique colors, they are used as-is.
+pub fn quantize(pixels: &[[u8; 3]], max: usize) -> Vec<[u8; 3]> {
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    for &pixel in pixels {
+        *histogram.entry(pixel).or_insert(0) += 1;
+    }
+    let mut colors: Vec<([u8; 3], u32)> = histogram.into_iter().collect();
+    colors.sort_unstable();
+
+    if colors.len() <= max {
+        return colors.into_iter().map(|(color, _)| color).collect();
+    }
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max {
+        // Split the box whose widest channel is the widest of them all
+        let widest = boxes.iter()
+            .enumerate()
+            .filter(|&(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .max_by_key(|&(_, (_, range))| range);
+        let (i, channel) = match widest {
+            Some((i, (channel, _))) => (i, channel),
+            None => break
+        };
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_unstable_by_key(|&(color, _)| color[channel]);
+
+        let total: u64 = colors.iter().map(|&(_, count)| count as u64).sum();
+        let mut seen = 0;
+        let mut median = colors.len() - 1;
+        for (j, &(_, count)) in colors.iter().enumerate() {
+            seen += count as u64;
+            if seen * 2 >= total {
+                median = j;
+                break;
             }
         }
+        // Both halves need at least one color
+        let split = (median + 1).min(colors.len() - 1);
+
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut palette: Vec<[u8; 3]> = boxes.iter().map(|colors| average(colors)).collect();
+    palette.sort_unstable();
+    palette
+}
+fn widest_channel(colors: &[([u8; 3], u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = colors.iter().map(|&(color, _)| color[channel]).min().unwrap();
+            let max = colors.iter().map(|&(color, _)| color[channel]).max().unwrap();
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+fn average(colors: &[([u8; 3], u32)]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    let mut total = 0u64;
+    for &(color, count) in colors {
+        for channel in 0..3 {
+            sum[channel] += color[channel] as u64 * count as u64;
+        }
+        total += count as u64;
+    }
+    [
+        ((sum[0] + total / 2) / total) as u8,
+        ((sum[1] + total / 2) / total) as u8,
+        ((sum[2] + total / 2) / total) as u8
+    ]
+}
 
-        let data: *mut c_uchar = data.as_mut_ptr() as *mut c_uchar;
+fn nearest(pixel: [u8; 3], palette: &[[u8; 3]]) -> u8 {
+    let distance = |color: &[u8; 3]| -> u32 {
+        (0..3)
+            .map(|i| {
+                let diff = pixel[i] as i32 - color[i] as i32;
+                (diff * diff) as u32
+            })
+            .sum()
+    };
+    palette.iter()
+        .enumerate()
+        .min_by_key(|&(_, color)| distance(color))
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
+
+/// Write the sixel data for an image whose pixels have been mapped to palette indices.
+/// Pixels without an index are transparent.
+pub fn encode<W: Write>(fmt: &mut W, width: usize, height: usize, palette: &[[u8; 3]], indices: &[Option<u8>]) -> io::Result<()> {
+    // The 1 makes pixels that aren't drawn keep their current color
+    write!(fmt, "\x1bP0;1q\"1;1;{};{}", width, height)?;
 
-        let mut output: Vec<u8> = Vec::new();
-        let mut sixel_output = ptr::null_mut();
+    for (i, color) in palette.iter().enumerate() {
+        let percent = |c: u8| (c as u32 * 100 + 127) / 255;
+        write!(fmt, "#{};2;{};{};{}", i, percent(color[0]), percent(color[1]), percent(color[2]))?;
+    }
 
-        if unsafe {
-            sixel_output_new(
-                &mut sixel_output,
-                write_fn,
-                &mut output as *mut _ as *mut c_void,
-                ptr::null_mut()
-            )
-        } != 0 {
-            return Err(io::Error::new(io::ErrorKind::Other, "sixel_output_new error"));
+    let mut line = Vec::with_capacity(width);
+    for band in 0..height.div_ceil(6) {
+        if band > 0 {
+            write!(fmt, "-")?;
         }
+        let top = band * 6;
+        let rows = (height - top).min(6);
 
-        let sixel_dither = unsafe { sixel_dither_get(SIXEL_BUILTIN_XTERM256) };
+        let mut used = [false; 256];
+        for index in indices[top * width..(top + rows) * width].iter().flatten() {
+            used[*index as usize] = true;
+        }
 
-        let result = unsafe {
-            sixel_encode(data, image.width() as i32, image.height() as i32, 0, sixel_dither, sixel_output)
-        };
+        let mut first = true;
+        for color in (0..palette.len()).filter(|&i| used[i]) {
+            line.clear();
+            for x in 0..width {
+                let mut bits = 0;
+                for row in 0..rows {
+                    if indices[(top + row) * width + x] == Some(color as u8) {
+                        bits |= 1 << row;
+                    }
+                }
+                line.push(b'?' + bits);
+            }
+            // Empty sixels at the end of a line can be left out
+            while line.last() == Some(&b'?') {
+                line.pop();
+            }
 
-        if result == 0 {
-            write!(fmt, "{}", ::std::str::from_utf8(&output).unwrap())
+            if !first {
+                write!(fmt, "$")?;
+            }
+            first = false;
+            write!(fmt, "#{}", color)?;
+            write_rle(fmt, &line)?;
+        }
+    }
+
+    write!(fmt, "\x1b\\")
+}
+fn write_rle<W: Write>(fmt: &mut W, line: &[u8]) -> io::Result<()> {
+    let mut i = 0;
+    while i < line.len() {
+        let c = line[i];
+        let run = line[i..].iter().take_while(|&&other| other == c).count();
+        if run > 3 {
+            write!(fmt, "!{}{}", run, c as char)?;
         } else {
-            Err(io::Error::new(io::ErrorKind::Other, "sixel_encode error"))
+            fmt.write_all(&line[i..i + run])?;
         }
+        i += run;
     }
-    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
-        (x * 10, y * 10)
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use converters::Converter;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_quantize() {
+        let pixels = [[255, 0, 0], [0, 0, 255], [255, 0, 0]];
+        assert_eq!(quantize(&pixels, 256), vec![[0, 0, 255], [255, 0, 0]]);
+
+        let pixels: Vec<[u8; 3]> = (0..=255).map(|i| [i, i, i]).collect();
+        assert_eq!(quantize(&pixels, 16).len(), 16);
+        assert_eq!(quantize(&pixels, 1), vec![[128, 128, 128]]);
+    }
+    #[test]
+    fn test_encode() {
+        let mut image = RgbImage::from_pixel(5, 7, Rgb([255, 0, 0]));
+        image.put_pixel(0, 0, Rgb([0, 0, 255]));
+        image.put_pixel(4, 6, Rgb([0, 0, 255]));
+
+        let output = Sixel::default().to_vec(&image);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\x1bP0;1q\"1;1;5;7#0;2;0;0;100#1;2;100;0;0\
+             #0@$#1}!4~\
+             -#0!4?@$#1!4@\
+             \x1b\\"
+        );
+    }
+    #[test]
+    fn test_rle() {
+        let mut output = Vec::new();
+        write_rle(&mut output, b"~~~AAAAB??????").unwrap();
+        assert_eq!(output, b"~~~!4AB!6?");
     }
 }
@@ -0,0 +1,42 @@
+use super::{
+    cells::CellLayout,
+    frame::Frame
+};
+
+use image::{GenericImage, Pixel};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug)]
+/// Writes a SVG image of what the terminal would show,
+/// for documentation and comparing output without a terminal
+pub struct Svg {
+    pub layout: CellLayout,
+    /// The size of a character in the SVG, in pixels
+    pub cell_width: u32,
+    pub cell_height: u32
+}
+impl Default for Svg {
+    fn default() -> Self {
+        Self {
+            layout: CellLayout::default(),
+            cell_width: 8,
+            cell_height: 16
+        }
+    }
+}
+
+impl super::Converter for Svg {
+    fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
+        where W: Write,
+              I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.display_frame(fmt, &Frame::new(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.layout.frame_cells(frame).write_svg(fmt, self.cell_width, self.cell_height)
+    }
+    fn actual_pos(&self, x: u32, y: u32) -> (u32, u32) {
+        self.layout.actual_pos(x, y)
+    }
+}
@@ -1,8 +1,36 @@
+use super::{
+    cells::{Cell, CellBuffer},
+    frame::{map_rows, Frame},
+    sgr::Color
+};
+
 use image::{GenericImage, Pixel};
 use std::io::{self, Write};
 
-#[derive(Clone, Copy, Debug)]
-pub struct TrueColor;
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrueColor {
+    /// Shorten runs of the same color with REP, which not all terminals support
+    pub rep: bool
+}
+impl TrueColor {
+    /// Convert an image to a grid of cells, one for each pixel
+    pub fn cells<I, P>(&self, image: &I) -> CellBuffer
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        self.frame_cells(&Frame::new(image))
+    }
+    /// Like `cells`, but reads the raw pixels directly
+    pub fn frame_cells(&self, frame: &Frame) -> CellBuffer {
+        let rows = map_rows(frame.height(), |y| {
+            (0..frame.width()).map(|x| match frame.visible(x, y) {
+                Some(pixel) => Cell { bg: Color::Rgb(pixel[0], pixel[1], pixel[2]), ..Cell::default() },
+                None => Cell::default()
+            }).collect()
+        });
+        CellBuffer::from_rows(frame.width(), rows)
+    }
+}
 
 impl super::Converter for TrueColor {
     fn display<W, I, P>(&self, fmt: &mut W, image: &I) -> io::Result<()>
@@ -10,16 +38,18 @@ impl super::Converter for TrueColor {
               I: GenericImage<Pixel = P>,
               P: Pixel<Subpixel = u8>
     {
-        for y in 0..image.height() {
-            if y > 0 {
-                write!(fmt, "\r\n")?;
-            }
-            for x in 0..image.width() {
-                let pixel = image.get_pixel(x, y).to_rgb().0;
-                write!(fmt, "\x1b[48;2;{};{};{}m ", pixel[0], pixel[1], pixel[2])?;
-            }
-            write!(fmt, "\x1b[0m")?;
-        }
-        Ok(())
+        self.cells(image).write_ansi(fmt, self.rep)
+    }
+    fn to_cells<I, P>(&self, image: &I) -> Option<CellBuffer>
+        where I: GenericImage<Pixel = P>,
+              P: Pixel<Subpixel = u8>
+    {
+        Some(self.cells(image))
+    }
+    fn display_frame<W: Write>(&self, fmt: &mut W, frame: &Frame) -> io::Result<()> {
+        self.frame_cells(frame).write_ansi(fmt, self.rep)
+    }
+    fn frame_to_cells(&self, frame: &Frame) -> Option<CellBuffer> {
+        Some(self.frame_cells(frame))
     }
 }
@@ -0,0 +1,175 @@
+//! Resampling images to the terminal's size, optionally in linear light
+
+use image::{imageops::{self, FilterType}, DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+
+/// An RGBA image with premultiplied float channels between 0 and 1
+type FloatImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// How pixels are blended when an image is resized
+pub enum Filter {
+    /// Pick the closest pixel. Fastest, but shrinking photos makes them noisy and jagged
+    #[default]
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Lanczos3,
+    /// Average all pixels that end up in the same one. Best for shrinking a lot
+    Box
+}
+impl Filter {
+    /// Return the filter from the image crate, or None for the box filter, which it doesn't have
+    pub fn filter_type(self) -> Option<FilterType> {
+        match self {
+            Filter::Nearest => Some(FilterType::Nearest),
+            Filter::Triangle => Some(FilterType::Triangle),
+            Filter::CatmullRom => Some(FilterType::CatmullRom),
+            Filter::Lanczos3 => Some(FilterType::Lanczos3),
+            Filter::Box => None
+        }
+    }
+}
+
+/// Resize an image to exactly width x height.
+/// With `linear`, pixels are blended in linear light instead of sRGB,
+/// which keeps bright details from getting darker as they're shrunk.
+pub fn resize(image: &DynamicImage, width: u32, height: u32, filter: Filter, linear: bool) -> DynamicImage {
+    match filter.filter_type() {
+        // Nearest doesn't blend anything, so linear light would make no difference
+        Some(FilterType::Nearest) => image.resize_exact(width, height, FilterType::Nearest),
+        Some(filter) if !linear => image.resize_exact(width, height, filter),
+        filter => {
+            let image = to_float(image, linear);
+            let image = match filter {
+                Some(filter) => imageops::resize(&image, width, height, filter),
+                None => box_resize(&image, width, height)
+            };
+            DynamicImage::ImageRgba8(from_float(&image, linear))
+        }
+    }
+}
+
+/// Convert an sRGB value between 0 and 1 to linear light
+fn decode(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+/// Convert a linear light value between 0 and 1 to sRGB
+fn encode(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_float(image: &DynamicImage, linear: bool) -> FloatImage {
+    let mut table = [0.0; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        *value = i as f32 / 255.0;
+        if linear {
+            *value = decode(*value);
+        }
+    }
+    let rgba = image.to_rgba();
+    FloatImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *rgba.get_pixel(x, y);
+        // Premultiply, so transparent pixels don't bleed their invisible color into their neighbors
+        let alpha = a as f32 / 255.0;
+        Rgba([table[r as usize] * alpha, table[g as usize] * alpha, table[b as usize] * alpha, alpha])
+    })
+}
+fn from_float(image: &FloatImage, linear: bool) -> RgbaImage {
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+        let alpha = a.clamp(0.0, 1.0);
+        let channel = |value: f32| {
+            let mut value = if alpha > 0.0 { (value / alpha).clamp(0.0, 1.0) } else { 0.0 };
+            if linear {
+                value = encode(value);
+            }
+            (value * 255.0).round() as u8
+        };
+        Rgba([channel(r), channel(g), channel(b), (alpha * 255.0).round() as u8])
+    })
+}
+
+/// Resize by averaging every source pixel that falls inside each new pixel
+fn box_resize(image: &FloatImage, width: u32, height: u32) -> FloatImage {
+    let (old_width, old_height) = image.dimensions();
+    // The source pixels from start to end (exclusive) that make up new pixel i
+    let span = |i: u32, old: u32, new: u32| {
+        let start = (i as u64 * old as u64 / new as u64) as u32;
+        let end = ((i as u64 + 1) * old as u64 / new as u64) as u32;
+        (start, end.max(start + 1).min(old))
+    };
+    FloatImage::from_fn(width, height, |x, y| {
+        let (x0, x1) = span(x, old_width, width);
+        let (y0, y1) = span(y, old_height, height);
+        let mut sum = [0.0; 4];
+        for y in y0..y1 {
+            for x in x0..x1 {
+                for (sum, &value) in sum.iter_mut().zip(&image.get_pixel(x, y).0) {
+                    *sum += value;
+                }
+            }
+        }
+        let count = ((x1 - x0) * (y1 - y0)) as f32;
+        Rgba([sum[0] / count, sum[1] / count, sum[2] / count, sum[3] / count])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Black and white vertical stripes, one pixel wide
+    fn stripes() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, _| {
+            if x % 2 == 0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+        }))
+    }
+
+    #[test]
+    fn test_box() {
+        let image = resize(&stripes(), 2, 2, Filter::Box, false).to_rgba();
+        assert!(image.pixels().all(|&pixel| pixel == Rgba([128, 128, 128, 255])));
+
+        // Half black and half white is about 188 in sRGB, not 128
+        let image = resize(&stripes(), 2, 2, Filter::Box, true).to_rgba();
+        assert!(image.pixels().all(|&pixel| pixel == Rgba([188, 188, 188, 255])));
+
+        // Nearest only ever picks one of the stripes
+        let image = resize(&stripes(), 2, 2, Filter::Nearest, true).to_rgba();
+        assert!(image.pixels().all(|&pixel| pixel[0] == 0 || pixel[0] == 255));
+    }
+    #[test]
+    fn test_transparency() {
+        // A transparent "red" pixel next to an opaque blue one shouldn't make it purple
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 { Rgba([255, 0, 0, 0]) } else { Rgba([0, 0, 255, 255]) }
+        }));
+        for &linear in &[false, true] {
+            let pixel = *resize(&image, 1, 1, Filter::Box, linear).to_rgba().get_pixel(0, 0);
+            assert_eq!(pixel, Rgba([0, 0, 255, 128]));
+        }
+    }
+    #[test]
+    fn test_roundtrip() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| Rgba([(x * 16) as u8, (y * 16) as u8, 77, 255])));
+        for &filter in &[Filter::Triangle, Filter::CatmullRom, Filter::Lanczos3, Filter::Box] {
+            for &linear in &[false, true] {
+                let resized = resize(&image, 16, 16, filter, linear).to_rgba();
+                // The image crate truncates instead of rounding with some filters
+                for (new, old) in resized.pixels().zip(image.to_rgba().pixels()) {
+                    for (&new, &old) in new.0.iter().zip(&old.0) {
+                        assert!((new as i32 - old as i32).abs() <= 1, "{:?} {}", filter, linear);
+                    }
+                }
+            }
+        }
+    }
+}
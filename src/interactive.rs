@@ -2,23 +2,29 @@
 
 #[cfg(feature = "gst")] use resizer::Sizer;
 #[cfg(feature = "termion")] use zoomer::Zoomer;
-use converters::Converter;
+use converters::{Background, Converter, Frame};
+#[cfg(feature = "termion")] use converters::CellBuffer;
+use filter::{self, Filter};
+use resizer::Gravity;
 
 #[cfg(feature = "gst")] use failure::Error;
 #[cfg(feature = "gst")] use gst::{self, prelude::*};
 #[cfg(feature = "gst")] use gst_app;
-#[cfg(feature = "gst")] use image::{self, ImageFormat, GenericImageView};
+#[cfg(feature = "gst")] use image::{self, ImageFormat};
+#[cfg(feature = "termion")] use image::GenericImageView;
 #[cfg(feature = "gst")] use std::sync::{Arc, Mutex};
 #[cfg(feature = "termion")] use std::io::Read;
-use image::{DynamicImage, imageops::FilterType};
+use image::DynamicImage;
 use std::io::{self, Write};
 #[cfg(feature = "termion")]
 use termion::{
+    clear,
     cursor,
     event::{Event, Key, MouseEvent, MouseButton},
     input::{MouseTerminal, TermRead},
     raw::IntoRawMode,
-    screen::AlternateScreen
+    screen::AlternateScreen,
+    style
 };
 
 #[cfg(feature = "termion")]
@@ -46,18 +52,144 @@ impl<W: Write> Write for Hide<W> {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Where the rich viewer and video player put an image that's smaller than the screen
+pub struct Placement {
+    /// The side or corner of the screen the image is pushed against
+    pub gravity: Gravity,
+    /// How many columns and rows to move the image away from that side or corner.
+    /// Centered images move right and down.
+    pub offset: (u16, u16)
+}
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            gravity: Gravity::TopLeft,
+            offset: (0, 0)
+        }
+    }
+}
+impl Placement {
+    /// Return the top left cell of an image that's `size` cells big, on a screen that's `screen`
+    /// cells big, counting from 0. The offset never pushes an image that fits off the screen.
+    pub fn position(&self, screen: (u16, u16), size: (u16, u16)) -> (u16, u16) {
+        let (x, y) = self.gravity.align((screen.0 as u32, screen.1 as u32), (size.0 as u32, size.1 as u32));
+        let (x, y) = (x as u16, y as u16);
+        let right = matches!(self.gravity, Gravity::TopRight | Gravity::Right | Gravity::BottomRight);
+        let bottom = matches!(self.gravity, Gravity::BottomLeft | Gravity::Bottom | Gravity::BottomRight);
+        let x = if right { x.saturating_sub(self.offset.0) } else { x.saturating_add(self.offset.0) };
+        let y = if bottom { y.saturating_sub(self.offset.1) } else { y.saturating_add(self.offset.1) };
+        (x.min(screen.0.saturating_sub(size.0)), y.min(screen.1.saturating_sub(size.1)))
+    }
+}
+
+#[cfg(feature = "termion")]
+#[derive(Debug, Default)]
+/// What's currently drawn on the screen, to only redraw what changed
+struct Screen {
+    /// Where the image is, as column, row, width and height in cells, counting from 0
+    area: Option<(u16, u16, u16, u16)>,
+    /// The image's cells, if the converter displays text
+    cells: Option<CellBuffer>
+}
+#[cfg(feature = "termion")]
+impl Screen {
+    /// Return the top left cell of the image, counting from 0
+    fn origin(&self) -> (u16, u16) {
+        self.area.map(|(x, y, _, _)| (x, y)).unwrap_or((0, 0))
+    }
+}
+#[cfg(feature = "termion")]
+/// Clear everything on a screen that's `screen` cells big, except for `area`,
+/// which is column, row, width and height counting from 0
+fn clear_around<W: Write>(fmt: &mut W, screen: (u16, u16), area: (u16, u16, u16, u16)) -> io::Result<()> {
+    let (x, y, width, height) = area;
+    write!(fmt, "{}", style::Reset)?;
+    for row in 0..screen.1 {
+        let line = row + 1;
+        if row < y || row >= y.saturating_add(height) {
+            write!(fmt, "{}{}", cursor::Goto(1, line), clear::CurrentLine)?;
+            continue;
+        }
+        if x > 0 {
+            // Clear from the start of the line to the column just left of the image
+            write!(fmt, "{}\x1b[1K", cursor::Goto(x, line))?;
+        }
+        if x.saturating_add(width) < screen.0 {
+            write!(fmt, "{}{}", cursor::Goto(x + width + 1, line), clear::UntilNewline)?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 /// A small interactive image viewer
-pub struct ImageViewer<C: Converter + Copy> {
+pub struct ImageViewer<C: Converter + Clone> {
     pub converter: C,
     pub width: u32,
-    pub height: u32
+    pub height: u32,
+    /// What to show behind transparent parts of the image
+    pub background: Background,
+    /// How pixels are blended when the image is resized
+    pub filter: Filter,
+    /// Blend pixels in linear light, see `filter::resize`
+    pub linear: bool,
+    /// Where to put the image in the rich viewer
+    pub placement: Placement,
+    /// Shorten runs of the same cell with REP in the rich viewer, see `SgrWriter`
+    pub rep: bool,
+    /// How much of the height fits on the screen. The rich viewer cuts taller images off
+    /// there and lets you scroll with the arrow keys.
+    pub viewport_height: Option<u32>
 }
-impl<C: Converter + Copy> ImageViewer<C> {
+impl<C: Converter + Clone> ImageViewer<C> {
     /// Simply resize and display an image
     pub fn display_image_quiet<W: Write>(&self, stdout: &mut W, image: &DynamicImage) -> io::Result<()> {
-        let image = image.resize_exact(self.width, self.height, FilterType::Nearest);
-        self.converter.display(stdout, &image)?;
+        let image = self.resize(image);
+        self.converter.display_frame(stdout, &Frame::from_image(&image))?;
+        stdout.flush()
+    }
+    /// Resize an image and put it on the background, ready to be converted
+    fn resize(&self, image: &DynamicImage) -> DynamicImage {
+        let mut image = filter::resize(image, self.width, self.height, self.filter, self.linear);
+        if self.background != Background::Terminal {
+            // Make checkerboard squares about two characters wide, which is roughly square
+            let mut rgba = image.to_rgba();
+            self.background.composite(&mut rgba, self.converter.actual_pos(2, 1));
+            image = DynamicImage::ImageRgba8(rgba);
+        }
+        image
+    }
+    #[cfg(feature = "termion")]
+    /// Draw a resized image where it's placed, and clear the rest of the screen if it moved.
+    /// Text is only redrawn where it changed by more than `tolerance`.
+    fn draw<W: Write>(&self, stdout: &mut W, screen: &mut Screen, image: &DynamicImage, tolerance: u8) -> io::Result<()> {
+        let size = termion::terminal_size().unwrap_or((80, 24));
+        let (cell_width, cell_height) = self.converter.actual_pos(1, 1);
+        let cells = (
+            image.width().div_ceil(cell_width.max(1)) as u16,
+            image.height().div_ceil(cell_height.max(1)) as u16
+        );
+        let (x, y) = self.placement.position(size, cells);
+
+        let area = (x, y, cells.0, cells.1);
+        if screen.area != Some(area) {
+            clear_around(stdout, size, area)?;
+            screen.area = Some(area);
+            screen.cells = None;
+        }
+
+        // Resized images and video frames are RGB or RGBA, so their pixels are read in place
+        let frame = Frame::from_image(image);
+
+        // Text has to be moved to the right column on every line, which write_diff does anyway
+        if let Some(cells) = self.converter.frame_to_cells(&frame) {
+            cells.write_diff(stdout, &mut screen.cells, (x + 1, y + 1), tolerance, self.rep)?;
+        } else {
+            screen.cells = None;
+            write!(stdout, "{}", cursor::Goto(x + 1, y + 1))?;
+            self.converter.display_frame(stdout, &frame)?;
+        }
         stdout.flush()
     }
     #[cfg(feature = "termion")]
@@ -71,16 +203,23 @@ impl<C: Converter + Copy> ImageViewer<C> {
         let stdout = Hide::from(stdout);
         let mut stdout = AlternateScreen::from(stdout);
 
-        let mut zoomer = Zoomer::new(self.converter);
+        let mut zoomer = Zoomer::new(self.converter.clone());
+        let mut screen = Screen::default();
+        let mut scroll = 0;
+        let max_scroll = self.viewport_height.map(|viewport| self.height.saturating_sub(viewport)).unwrap_or(0);
+        let (_, line_height) = self.converter.actual_pos(0, 1);
 
-        let mut draw = |zoomer: &Zoomer<_>| -> io::Result<()> {
+        let mut draw = |zoomer: &Zoomer<_>, screen: &mut Screen, scroll: u32| -> io::Result<()> {
             let image = zoomer.crop(image, self.width, self.height);
+            let image = self.resize(&image);
+            let image = match self.viewport_height {
+                Some(viewport) if viewport < self.height => image.crop_imm(0, scroll, self.width, viewport),
+                _ => image
+            };
 
-            write!(stdout, "{}", cursor::Goto(1, 1)).unwrap();
-            self.display_image_quiet(&mut stdout, &image)?;
-            Ok(())
+            self.draw(&mut stdout, screen, &image, 0)
         };
-        draw(&zoomer)?;
+        draw(&zoomer, &mut screen, scroll)?;
 
         for event in stdin.events() {
             match event? {
@@ -88,6 +227,19 @@ impl<C: Converter + Copy> ImageViewer<C> {
                 Event::Key(Key::Char('q')) => {
                     return Ok(());
                 },
+                Event::Key(key @ Key::Up) |
+                Event::Key(key @ Key::Down) |
+                Event::Key(key @ Key::PageUp) |
+                Event::Key(key @ Key::PageDown) => {
+                    let page = self.viewport_height.unwrap_or(0);
+                    scroll = match key {
+                        Key::Up => scroll.saturating_sub(line_height),
+                        Key::Down => scroll + line_height,
+                        Key::PageUp => scroll.saturating_sub(page),
+                        _ => scroll + page
+                    }.min(max_scroll);
+                    draw(&zoomer, &mut screen, scroll)?;
+                },
                 Event::Key(Key::Char(c)) => {
                     let (mut x, mut y) = zoomer.pos();
                     let level = zoomer.level();
@@ -101,10 +253,12 @@ impl<C: Converter + Copy> ImageViewer<C> {
                         _   => ()
                     }
                     zoomer.set_pos(x, y);
-                    draw(&zoomer)?;
+                    draw(&zoomer, &mut screen, scroll)?;
                 },
                 Event::Mouse(MouseEvent::Press(btn, x, y)) => {
                     let level = zoomer.level();
+                    let (left, top) = screen.origin();
+                    let (x, y) = (x.saturating_sub(left), y.saturating_sub(top));
 
                     match btn {
                         MouseButton::Left => zoomer.drag_start(x, y),
@@ -122,11 +276,12 @@ impl<C: Converter + Copy> ImageViewer<C> {
                         },
                         _ => ()
                     }
-                    draw(&zoomer)?;
+                    draw(&zoomer, &mut screen, scroll)?;
                 },
                 Event::Mouse(MouseEvent::Hold(x, y)) => {
-                    zoomer.drag_move(x, y);
-                    draw(&zoomer)?;
+                    let (left, top) = screen.origin();
+                    zoomer.drag_move(x.saturating_sub(left), y.saturating_sub(top));
+                    draw(&zoomer, &mut screen, scroll)?;
                 },
                 Event::Mouse(MouseEvent::Release(..)) => {
                     zoomer.drag_stop();
@@ -150,13 +305,24 @@ pub enum VideoError {
 /// A GStreamer-based interactive video player.
 /// Because of some internal threading, this is cloned inside the play_video function.
 /// So you will probably want to keep the converter and sizer small.
-pub struct VideoPlayer<C: Converter + Copy + Send + 'static, S: Sizer + Clone + Send + 'static> {
+pub struct VideoPlayer<C: Converter + Clone + Send + 'static, S: Sizer + Clone + Send + 'static> {
     pub converter: C,
     pub sizer: S,
-    pub rate: u8
+    pub rate: u8,
+    /// How pixels are blended when frames are resized
+    pub filter: Filter,
+    /// Blend pixels in linear light, see `filter::resize`
+    pub linear: bool,
+    /// Where to put the video on the screen
+    pub placement: Placement,
+    /// Shorten runs of the same cell with REP, see `SgrWriter`
+    pub rep: bool,
+    /// How much a color may change before a cell is redrawn.
+    /// Only converters that display text redraw single cells.
+    pub tolerance: u8
 }
 #[cfg(feature = "gst")]
-impl<C: Converter + Copy + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPlayer<C, S> {
+impl<C: Converter + Clone + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPlayer<C, S> {
     fn image_from_sample(&self, sample: &gst::sample::SampleRef) -> Option<DynamicImage> {
         let buffer = sample.get_buffer()?;
         let map = buffer.map_readable().ok()?;
@@ -166,20 +332,37 @@ impl<C: Converter + Copy + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPla
         &self,
         stdout: &mut W,
         zoomer: &Zoomer<C>,
+        screen: &mut Screen,
         image: &mut DynamicImage
     ) {
         let (width, height) = self.sizer.get_size(image.width(), image.height());
+        let (x, y, crop_width, crop_height) = self.sizer.crop(image.width(), image.height());
+        // Crop a copy, since a paused frame is drawn again every time it's zoomed or moved
+        let mut cropped;
+        let image = if (crop_width, crop_height) != image.dimensions() {
+            cropped = image.crop_imm(x, y, crop_width, crop_height);
+            &mut cropped
+        } else {
+            image
+        };
 
         let image = zoomer.crop(image, width, height);
 
         let viewer = ImageViewer {
-            converter: self.converter,
+            converter: self.converter.clone(),
             width,
-            height
+            height,
+            background: Background::Terminal,
+            filter: self.filter,
+            linear: self.linear,
+            placement: self.placement,
+            rep: self.rep,
+            viewport_height: None
         };
 
-        write!(stdout, "{}", cursor::Goto(1, 1)).unwrap();
-        let _ = viewer.display_image_quiet(&mut *stdout, &image);
+        // Only redraw what changed since the last frame, if the converter allows that
+        let image = viewer.resize(&image);
+        let _ = viewer.draw(stdout, screen, &image, self.tolerance);
     }
     /// Play the video on specified uri. Use file:// links for file paths.
     pub fn play_video<R, W>(&self, stdin: &mut R, stdout: W, uri: &str) -> Result<(), Error>
@@ -213,7 +396,8 @@ impl<C: Converter + Copy + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPla
         source.set_property("uri", &uri)?;
         source.set_property("video-sink", &bin.upcast::<gst::Element>())?;
 
-        let zoomer = Arc::new(Mutex::new(Zoomer::new(self.converter)));
+        let zoomer = Arc::new(Mutex::new(Zoomer::new(self.converter.clone())));
+        let screen = Arc::new(Mutex::new(Screen::default()));
 
         let stdout = stdout.into_raw_mode()?;
         let stdout = MouseTerminal::from(stdout);
@@ -228,13 +412,15 @@ impl<C: Converter + Copy + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPla
                 .new_sample({
                     let stdout = Arc::clone(&stdout);
                     let zoomer = Arc::clone(&zoomer);
+                    let screen = Arc::clone(&screen);
                     move |sink| {
                         let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
                         let mut stdout = stdout.lock().unwrap();
                         let zoomer = zoomer.lock().unwrap();
+                        let mut screen = screen.lock().unwrap();
                         match clone.image_from_sample(&sample) {
                             Some(mut image) => {
-                                clone.display_image(&mut *stdout, &zoomer, &mut image);
+                                clone.display_image(&mut *stdout, &zoomer, &mut screen, &mut image);
                                 Ok(gst::FlowSuccess::Ok)
                             },
                             None => Err(gst::FlowError::Error)
@@ -327,12 +513,14 @@ impl<C: Converter + Copy + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPla
                     }
                     zoomer.set_pos(x, y);
                     if let Some(ref mut frame) = frame {
-                        self.display_image(&mut *stdout.lock().unwrap(), &zoomer, frame);
+                        self.display_image(&mut *stdout.lock().unwrap(), &zoomer, &mut screen.lock().unwrap(), frame);
                     }
                 },
                 Event::Mouse(MouseEvent::Press(btn, x, y)) => {
                     let mut zoomer = zoomer.lock().unwrap();
                     let level = zoomer.level();
+                    let (left, top) = screen.lock().unwrap().origin();
+                    let (x, y) = (x.saturating_sub(left), y.saturating_sub(top));
 
                     match btn {
                         MouseButton::Left => zoomer.drag_start(x, y),
@@ -351,14 +539,15 @@ impl<C: Converter + Copy + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPla
                         _ => ()
                     }
                     if let Some(ref mut frame) = frame {
-                        self.display_image(&mut *stdout.lock().unwrap(), &zoomer, frame);
+                        self.display_image(&mut *stdout.lock().unwrap(), &zoomer, &mut screen.lock().unwrap(), frame);
                     }
                 },
                 Event::Mouse(MouseEvent::Hold(x, y)) => {
                     let mut zoomer = zoomer.lock().unwrap();
-                    zoomer.drag_move(x, y);
+                    let (left, top) = screen.lock().unwrap().origin();
+                    zoomer.drag_move(x.saturating_sub(left), y.saturating_sub(top));
                     if let Some(ref mut frame) = frame {
-                        self.display_image(&mut *stdout.lock().unwrap(), &zoomer, frame);
+                        self.display_image(&mut *stdout.lock().unwrap(), &zoomer, &mut screen.lock().unwrap(), frame);
                     }
                 },
                 Event::Mouse(MouseEvent::Release(..)) => {
@@ -371,3 +560,41 @@ impl<C: Converter + Copy + Send + Sync, S: Sizer + Clone + Send + Sync> VideoPla
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placement() {
+        let placement = Placement::default();
+        assert_eq!(placement.position((80, 24), (40, 10)), (0, 0));
+
+        let placement = Placement { gravity: Gravity::Center, offset: (0, 0) };
+        assert_eq!(placement.position((80, 24), (40, 10)), (20, 7));
+        let placement = Placement { offset: (5, 1), ..placement };
+        assert_eq!(placement.position((80, 24), (40, 10)), (25, 8));
+
+        let placement = Placement { gravity: Gravity::BottomRight, offset: (2, 3) };
+        assert_eq!(placement.position((80, 24), (40, 10)), (38, 11));
+
+        // Offsets don't push images off the screen
+        let placement = Placement { gravity: Gravity::TopLeft, offset: (100, 100) };
+        assert_eq!(placement.position((80, 24), (40, 10)), (40, 14));
+        assert_eq!(placement.position((80, 24), (100, 30)), (0, 0));
+    }
+    #[cfg(feature = "termion")]
+    #[test]
+    fn test_clear_around() {
+        let mut output = Vec::new();
+        clear_around(&mut output, (10, 4), (2, 1, 5, 2)).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\x1b[m\
+             \x1b[1;1H\x1b[2K\
+             \x1b[2;2H\x1b[1K\x1b[2;8H\x1b[K\
+             \x1b[3;2H\x1b[1K\x1b[3;8H\x1b[K\
+             \x1b[4;1H\x1b[2K"
+        );
+    }
+}
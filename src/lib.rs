@@ -1,10 +1,16 @@
 #[cfg(feature = "gst")] #[macro_use] extern crate failure;
 #[cfg(feature = "gst")] extern crate gstreamer as gst;
 #[cfg(feature = "gst")] extern crate gstreamer_app as gst_app;
+#[cfg(feature = "rayon")] extern crate rayon;
+#[cfg(feature = "termion")] extern crate libc;
 #[cfg(feature = "termion")] extern crate termion;
+extern crate base64;
 extern crate image;
 
+pub mod capabilities;
 pub mod converters;
+pub mod filter;
 pub mod interactive;
+#[cfg(feature = "termion")] pub mod query;
 pub mod resizer;
 pub mod zoomer;
@@ -9,15 +9,19 @@ extern crate termplay;
 use clap::{Arg, App};
 use failure::Error;
 use image::GenericImageView;
-use std::io;
+use std::{io, sync::Arc};
+#[cfg(feature = "termion")] use std::time::Duration;
 #[cfg(feature = "gst")] use termplay::interactive::VideoPlayer;
+#[cfg(feature = "termion")] use termplay::query;
 use termplay::{
     converters::*,
-    interactive::ImageViewer,
-    resizer::{Sizer, StandardSizer}
+    filter::Filter,
+    interactive::{ImageViewer, Placement},
+    resizer::*
 };
 
 fn main() -> Result<(), Error> {
+    let registry = Registry::builtin();
     let app =
         App::new(crate_name!())
             .version(crate_version!())
@@ -37,17 +41,74 @@ fn main() -> Result<(), Error> {
                 .short("h")
                 .long("height")
                 .takes_value(true))
-            .arg(Arg::with_name("ratio")
-                .help("Sets the terminal font ratio")
-                .long("ratio")
+            .arg(Arg::with_name("fit")
+                .help("Decides how the image fills the width and height. Cover crops what doesn't fit, \
+                       width lets you scroll down tall images (not videos)")
+                .long("fit")
+                .takes_value(true)
+                .possible_values(&["contain", "cover", "stretch", "width", "height"])
+                .default_value("contain"))
+            .arg(Arg::with_name("gravity")
+                .help("Decides which part of the image is kept when it's cropped (cover and height only)")
+                .long("gravity")
+                .takes_value(true)
+                .possible_values(GRAVITIES)
+                .default_value("center"))
+            .arg(Arg::with_name("cell-aspect")
+                .help("Sets how many times taller than wide a terminal cell is, like 2 or 0.5, \
+                       instead of asking the terminal how big its cells are")
+                .long("cell-aspect")
                 .takes_value(true))
+            .arg(Arg::with_name("background")
+                .help("Sets what to show behind transparent images: terminal, checkerboard, or a hex color like #ffffff")
+                .long("background")
+                .takes_value(true)
+                .default_value("terminal"))
             .arg(Arg::with_name("converter")
                 .help("Decides how the image should be displayed")
                 .short("c")
                 .long("converter")
                 .takes_value(true)
-                .possible_values(&["color256", "halfblock", "sixel", "truecolor"])
-                .default_value("halfblock"))
+                .possible_values(&registry.names())
+                .default_value("auto"))
+            .arg(Arg::with_name("dither")
+                .help("Dithers colors that don't fit the palette (ansi16, color256 and sixel only). Bayer is recommended for video")
+                .long("dither")
+                .takes_value(true)
+                .possible_values(&["none", "floyd-steinberg", "atkinson", "bayer"])
+                .default_value("none"))
+            .arg(Arg::with_name("filter")
+                .help("Decides how pixels are blended when resizing. Box is best for shrinking photos, \
+                       nearest is the fastest")
+                .long("filter")
+                .takes_value(true)
+                .possible_values(&["nearest", "triangle", "catmull-rom", "lanczos3", "box"])
+                .default_value("nearest"))
+            .arg(Arg::with_name("linear")
+                .help("Blends pixels in linear light, which keeps bright details from getting darker when shrunk \
+                       (not with the nearest filter)")
+                .long("linear"))
+            .arg(Arg::with_name("html-classes")
+                .help("Styles colors with CSS classes instead of inline styles (html only)")
+                .long("html-classes"))
+            .arg(Arg::with_name("metric")
+                .help("Decides how colors are matched against the palette (ansi16 and color256 only)")
+                .long("metric")
+                .takes_value(true)
+                .possible_values(&["manhattan", "cie76", "ciede2000", "oklab"])
+                .default_value("manhattan"))
+            .arg(Arg::with_name("palette")
+                .help("Sets the 16 terminal colors as comma separated hex colors, like #000000,#800000,... (ansi16 only)")
+                .long("palette")
+                .takes_value(true))
+            .arg(Arg::with_name("tolerance")
+                .help("Sets how much a color may change before it's redrawn during video playback, to use less bandwidth")
+                .long("tolerance")
+                .takes_value(true)
+                .default_value("0"))
+            .arg(Arg::with_name("rep")
+                .help("Repeats characters with REP to make output smaller, which not all terminals support (color256, halfblock and truecolor only)")
+                .long("rep"))
             .arg(Arg::with_name("rate")
                 .help("Sets the framerate")
                 .short("r")
@@ -59,23 +120,122 @@ fn main() -> Result<(), Error> {
         .arg(Arg::with_name("quiet")
             .help("Ignores all the nice TUI things for simple image viewing")
             .short("q")
-            .long("quiet"));
+            .long("quiet"))
+        .arg(Arg::with_name("query-palette")
+            .help("Asks the terminal what its palette looks like (ansi16 and color256 only)")
+            .long("query-palette"))
+        .arg(Arg::with_name("position")
+            .help("Decides where on the screen the image is shown, when it's smaller than the screen (not with --quiet)")
+            .long("position")
+            .takes_value(true)
+            .possible_values(GRAVITIES)
+            .default_value("top-left"))
+        .arg(Arg::with_name("offset")
+            .help("Moves the image this many columns and rows away from its position, like 4,2 (not with --quiet)")
+            .long("offset")
+            .takes_value(true));
     let options = app.get_matches();
 
     let path = options.value_of_os("path").unwrap();
 
-    let converter = match options.value_of("converter").unwrap() {
-        #[cfg(feature = "sixel")] "sixel" => DynamicConverter::Sixel,
-        "color256"  => DynamicConverter::Color256,
-        "halfblock"  => DynamicConverter::HalfBlock,
-        "truecolor" => DynamicConverter::TrueColor,
+    let metric = match options.value_of("metric").unwrap() {
+        "manhattan" => ColorMetric::Manhattan,
+        "cie76"     => ColorMetric::Cie76,
+        "ciede2000" => ColorMetric::Ciede2000,
+        "oklab"     => ColorMetric::Oklab,
+        _ => unreachable!()
+    };
+
+    let dither = match options.value_of("dither").unwrap() {
+        "none"            => Dither::None,
+        "floyd-steinberg" => Dither::FloydSteinberg,
+        "atkinson"        => Dither::Atkinson,
+        "bayer"           => Dither::Bayer,
+        _ => unreachable!()
+    };
+
+    let filter = match options.value_of("filter").unwrap() {
+        "nearest"     => Filter::Nearest,
+        "triangle"    => Filter::Triangle,
+        "catmull-rom" => Filter::CatmullRom,
+        "lanczos3"    => Filter::Lanczos3,
+        "box"         => Filter::Box,
         _ => unreachable!()
     };
+    let linear = options.is_present("linear");
 
-    let ratio = value_t!(options, "ratio", u8).ok();
-    if ratio == Some(0) {
-        bail!("ratio can't be zero");
+    #[cfg(feature = "termion")]
+    let queried = if options.is_present("query-palette") {
+        query::query_colors(256, Duration::from_millis(500))
+            .ok()
+            .map(|colors| (colors.palette_16(), colors.palette_256()))
+    } else {
+        None
+    };
+    #[cfg(not(feature = "termion"))]
+    let queried: Option<(_, Vec<_>)> = None;
+
+    let rep = options.is_present("rep");
+
+    let cell_aspect = match options.value_of("cell-aspect") {
+        Some(aspect) => match aspect.parse::<f64>() {
+            Ok(aspect) if aspect.is_finite() && aspect > 0.0 => Some(aspect),
+            _ => bail!("cell aspect must be a positive number")
+        },
+        None => None
+    };
+
+    // A manually set cell aspect always wins over whatever the terminal says
+    #[cfg(feature = "termion")]
+    let detected = if cell_aspect.is_none() {
+        query::cell_size(Duration::from_millis(500))
+    } else {
+        None
+    };
+    #[cfg(not(feature = "termion"))]
+    let detected = None;
+    // Converters that draw pixels need whole pixels, so the aspect is only approximated there
+    let cell_size = cell_aspect
+        .map(|aspect| (DEFAULT_CELL_SIZE.0, ((DEFAULT_CELL_SIZE.0 as f64 * aspect).round() as u32).max(1)))
+        .or(detected);
+
+    let mut converter_options = ConverterOptions {
+        metric,
+        dither,
+        rep,
+        palette: None,
+        quantizer: None,
+        html_style: if options.is_present("html-classes") { HtmlStyle::Classes } else { HtmlStyle::Inline },
+        cell_size
+    };
+    if let Some((palette, palette_256)) = queried {
+        converter_options.palette = Some(palette);
+        converter_options.quantizer = Some(Arc::new(Quantizer::new(&palette_256, metric)));
     }
+    if let Some(list) = options.value_of("palette") {
+        let colors = list.split(',')
+            .map(parse_color)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format_err!("palette contains an invalid color"))?;
+        let mut palette = [(0, 0, 0); 16];
+        if colors.len() != palette.len() {
+            bail!("palette must have exactly 16 colors");
+        }
+        palette.copy_from_slice(&colors);
+        converter_options.palette = Some(palette);
+    }
+
+    let converter = registry.create(options.value_of("converter").unwrap(), &converter_options).unwrap();
+
+    let background = match options.value_of("background").unwrap() {
+        "terminal" => Background::Terminal,
+        "checkerboard" => Background::Checkerboard,
+        color => {
+            let (r, g, b) = parse_color(color)
+                .ok_or_else(|| format_err!("background must be terminal, checkerboard or a hex color"))?;
+            Background::Color(r, g, b)
+        }
+    };
 
     #[cfg(feature = "termion")]
     let (width, height) = termion::terminal_size().map(|(w, h)| (w as u32, h as u32)).unwrap_or((80, 24));
@@ -91,11 +251,47 @@ fn main() -> Result<(), Error> {
         height = h;
     }
 
-    let sizer = StandardSizer {
-        new_width: width,
-        new_height: height,
-        ratio
+    let gravity = parse_gravity(options.value_of("gravity").unwrap());
+
+    let fit = options.value_of("fit").unwrap();
+    let sizer: Arc<dyn Sizer + Send + Sync> = match fit {
+        "contain" => Arc::new(StandardSizer { new_width: width, new_height: height }),
+        "cover"   => Arc::new(CoverSizer { new_width: width, new_height: height, gravity }),
+        "stretch" => Arc::new(StretchSizer { new_width: width, new_height: height }),
+        "width"   => Arc::new(FitWidthSizer { new_width: width }),
+        "height"  => Arc::new(FitHeightSizer { new_width: width, new_height: height, gravity }),
+        _ => unreachable!()
+    };
+    let cell_size = cell_aspect
+        .map(|aspect| (DEFAULT_CELL_SIZE.0 as f64, DEFAULT_CELL_SIZE.0 as f64 * aspect))
+        .or_else(|| detected.map(|(w, h)| (w as f64, h as f64)));
+    let sizer: Arc<dyn Sizer + Send + Sync> = match cell_size {
+        Some(cell_size) => Arc::new(CellAspectSizer::new(sizer, cell_size, converter.actual_pos(1, 1))),
+        None => sizer
+    };
+    let viewport_height = if fit == "width" { Some(height) } else { None };
+
+    #[cfg(feature = "termion")] {
+        if options.is_present("quiet") && (options.occurrences_of("position") > 0 || options.is_present("offset")) {
+            bail!("position and offset only work in the interactive viewer, not with quiet");
+        }
+    }
+    #[cfg(feature = "termion")]
+    let placement = Placement {
+        gravity: parse_gravity(options.value_of("position").unwrap()),
+        offset: match options.value_of("offset") {
+            Some(offset) => {
+                let mut parts = offset.split(',').map(|part| part.trim().parse::<u16>().ok());
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(Some(columns)), Some(Some(rows)), None) => (columns, rows),
+                    _ => bail!("offset must be columns and rows, like 4,2")
+                }
+            },
+            None => (0, 0)
+        }
     };
+    #[cfg(not(feature = "termion"))]
+    let placement = Placement::default();
 
     let mut stdout = io::stdout();
     #[cfg(feature = "termion")]
@@ -105,15 +301,26 @@ fn main() -> Result<(), Error> {
 
     match image::open(path) {
         Ok(image) => {
+            let (width, height) = sizer.get_size(image.width(), image.height());
+            let (x, y, crop_width, crop_height) = sizer.crop(image.width(), image.height());
+            let image = if (crop_width, crop_height) != image.dimensions() {
+                image.crop_imm(x, y, crop_width, crop_height)
+            } else {
+                image
+            };
             #[cfg(feature = "termion")]
             let mut image = image;
 
-            let (width, height) = sizer.get_size(image.width(), image.height());
-
             let viewer = ImageViewer {
                 converter,
                 width,
-                height
+                height,
+                background,
+                filter,
+                linear,
+                placement,
+                rep,
+                viewport_height
             };
 
             #[cfg(feature = "termion")]
@@ -143,6 +350,10 @@ fn main() -> Result<(), Error> {
             if rate == 0 {
                 bail!("rate can't be zero");
             }
+            // Only the image viewer can scroll, so tall frames would run off the screen
+            if fit == "width" {
+                bail!("fit width only works for images, not videos");
+            }
 
             let path_str = path.to_str();
 
@@ -161,13 +372,51 @@ fn main() -> Result<(), Error> {
                 Cow::Owned(uri)
             };
 
+            let tolerance = value_t!(options, "tolerance", u8).unwrap_or_else(|e| e.exit());
+
             let player = VideoPlayer {
                 converter,
                 sizer,
-                rate
+                rate,
+                filter,
+                linear,
+                placement,
+                rep,
+                tolerance
             };
             player.play_video(&mut stdin, stdout, &uri)
         },
         Err(err) => Err(err.into())
     }
 }
+
+/// The names of all `Gravity` values, for the options that take one
+const GRAVITIES: &[&str] = &[
+    "center", "top", "bottom", "left", "right",
+    "top-left", "top-right", "bottom-left", "bottom-right"
+];
+
+/// Parse one of `GRAVITIES`
+fn parse_gravity(gravity: &str) -> Gravity {
+    match gravity {
+        "center"       => Gravity::Center,
+        "top"          => Gravity::Top,
+        "bottom"       => Gravity::Bottom,
+        "left"         => Gravity::Left,
+        "right"        => Gravity::Right,
+        "top-left"     => Gravity::TopLeft,
+        "top-right"    => Gravity::TopRight,
+        "bottom-left"  => Gravity::BottomLeft,
+        "bottom-right" => Gravity::BottomRight,
+        _ => unreachable!()
+    }
+}
+/// Parse a hex color like #ff8000
+fn parse_color(color: &str) -> Option<(u8, u8, u8)> {
+    let color = color.trim().trim_start_matches('#');
+    if color.len() != 6 || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(color, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
@@ -0,0 +1,240 @@
+//! Ask the terminal about itself, by sending escape sequences and reading the replies
+
+use converters::COLORS;
+
+use libc;
+use std::{
+    io::{self, Read, Write},
+    mem,
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant}
+};
+use termion::{self, raw::IntoRawMode};
+
+/// Primary Device Attributes. All terminals reply to this,
+/// so it's sent last to know when all other replies have arrived.
+const DA1: &[u8] = b"\x1b[c";
+
+/// Send a query to the terminal and return all replies, including the one to DA1.
+/// Gives up after `timeout` if the terminal doesn't answer.
+pub fn query(query: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let tty = termion::get_tty()?;
+    let fd = tty.as_raw_fd();
+    let mut tty = tty.into_raw_mode()?;
+
+    tty.write_all(query)?;
+    tty.write_all(DA1)?;
+    tty.flush()?;
+
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+    let mut buf = [0; 1024];
+
+    while !has_da1(&reply) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0
+        };
+        let millis = (deadline - now).as_millis().max(1) as libc::c_int;
+        match unsafe { libc::poll(&mut pollfd, 1, millis) } {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => break,
+            _ => {
+                let read = tty.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                reply.extend_from_slice(&buf[..read]);
+            }
+        }
+    }
+    Ok(reply)
+}
+/// Return the parameters of the DA1 reply, if there is one
+pub fn da1(reply: &[u8]) -> Option<Vec<u32>> {
+    let start = find(reply, b"\x1b[?")? + 3;
+    let len = reply[start..].iter().position(|&b| !(b.is_ascii_digit() || b == b';'))?;
+    if reply[start + len] != b'c' {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&reply[start..start + len])
+            .split(';')
+            .filter_map(|param| param.parse().ok())
+            .collect()
+    )
+}
+fn has_da1(reply: &[u8]) -> bool {
+    da1(reply).is_some()
+}
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// The colors a terminal actually displays
+pub struct TerminalColors {
+    pub foreground: Option<(u8, u8, u8)>,
+    pub background: Option<(u8, u8, u8)>,
+    /// The palette, with `None` for colors the terminal didn't report
+    pub palette: Vec<Option<(u8, u8, u8)>>
+}
+impl TerminalColors {
+    /// Parse OSC 4, 10 and 11 replies
+    pub fn parse(reply: &[u8], count: usize) -> Self {
+        let mut colors = Self {
+            foreground: None,
+            background: None,
+            palette: vec![None; count]
+        };
+        let reply = String::from_utf8_lossy(reply);
+        for osc in reply.split("\x1b]").skip(1) {
+            let osc = osc.split(['\x07', '\x1b']).next().unwrap();
+            let mut params = osc.split(';');
+            match (params.next(), params.next(), params.next()) {
+                (Some("4"), Some(index), Some(color)) => {
+                    if let (Ok(index), Some(color)) = (index.parse::<usize>(), parse_rgb(color)) {
+                        if index < count {
+                            colors.palette[index] = Some(color);
+                        }
+                    }
+                },
+                (Some("10"), Some(color), None) => colors.foreground = parse_rgb(color),
+                (Some("11"), Some(color), None) => colors.background = parse_rgb(color),
+                _ => ()
+            }
+        }
+        colors
+    }
+    /// Return the 16 color palette, using xterm's colors for those that are missing
+    pub fn palette_16(&self) -> [(u8, u8, u8); 16] {
+        let mut palette = [(0, 0, 0); 16];
+        for (i, color) in palette.iter_mut().enumerate() {
+            *color = self.palette.get(i).and_then(|&c| c).unwrap_or(COLORS[i]);
+        }
+        palette
+    }
+    /// Return the 256 color palette, using xterm's colors for those that are missing
+    pub fn palette_256(&self) -> Vec<(u8, u8, u8)> {
+        COLORS.iter()
+            .enumerate()
+            .map(|(i, &default)| self.palette.get(i).and_then(|&c| c).unwrap_or(default))
+            .collect()
+    }
+}
+/// Parse a color like rgb:ffff/8080/0000, where each channel has 1 to 4 hex digits
+fn parse_rgb(color: &str) -> Option<(u8, u8, u8)> {
+    if !color.starts_with("rgb:") {
+        return None;
+    }
+    let mut channels = color[4..].split('/').map(|channel| {
+        if channel.is_empty() || channel.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = (1u32 << (channel.len() * 4)) - 1;
+        Some(((value * 255 + max / 2) / max) as u8)
+    });
+    let color = (channels.next()??, channels.next()??, channels.next()??);
+    if channels.next().is_some() {
+        return None;
+    }
+    Some(color)
+}
+
+/// Query the terminal's foreground, background and the first `count` palette colors
+pub fn query_colors(count: usize, timeout: Duration) -> io::Result<TerminalColors> {
+    let mut request = Vec::new();
+    for i in 0..count {
+        write!(request, "\x1b]4;{};?\x07", i)?;
+    }
+    request.extend_from_slice(b"\x1b]10;?\x07\x1b]11;?\x07");
+
+    let reply = query(&request, timeout)?;
+    Ok(TerminalColors::parse(&reply, count))
+}
+
+/// Return the size of a terminal cell in pixels, as width and height.
+/// Asks the kernel first, which knows if the terminal told it, then the terminal itself
+/// with `CSI 16 t` and `CSI 14 t`. Returns None if neither knows.
+pub fn cell_size(timeout: Duration) -> Option<(u32, u32)> {
+    let tty = termion::get_tty().ok()?;
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(tty.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } == 0 {
+        let pixels = (size.ws_xpixel as u32, size.ws_ypixel as u32);
+        if let Some(cell_size) = per_cell(pixels, (size.ws_col as u32, size.ws_row as u32)) {
+            return Some(cell_size);
+        }
+    }
+    let (columns, rows) = termion::terminal_size().ok()?;
+    let reply = query(b"\x1b[16t\x1b[14t", timeout).ok()?;
+    parse_cell_size(&reply, (columns as u32, rows as u32))
+}
+/// Parse the replies to `CSI 16 t` (the cell size) or `CSI 14 t` (the size of the whole
+/// text area, which is divided by `cells`). Sizes are sent as height first.
+pub fn parse_cell_size(reply: &[u8], cells: (u32, u32)) -> Option<(u32, u32)> {
+    let reply = String::from_utf8_lossy(reply);
+    let mut text_area = None;
+    for csi in reply.split("\x1b[").skip(1) {
+        let end = match csi.find('t') {
+            Some(end) => end,
+            None => continue
+        };
+        let params: Vec<u32> = match csi[..end].split(';').map(|param| param.parse().ok()).collect() {
+            Some(params) => params,
+            None => continue
+        };
+        match params[..] {
+            [6, height, width] if width > 0 && height > 0 => return Some((width, height)),
+            [4, height, width] => text_area = per_cell((width, height), cells),
+            _ => ()
+        }
+    }
+    text_area
+}
+/// Divide the size of the text area in pixels by its size in cells.
+/// Returns None if either is unknown, or if a cell would be smaller than a pixel,
+/// since nothing can be drawn in cells that are 0 pixels big.
+fn per_cell(pixels: (u32, u32), cells: (u32, u32)) -> Option<(u32, u32)> {
+    if cells.0 == 0 || cells.1 == 0 {
+        return None;
+    }
+    Some((pixels.0 / cells.0, pixels.1 / cells.1)).filter(|&(width, height)| width > 0 && height > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let reply = b"\x1b]4;0;rgb:2828/2828/2828\x07\
+                      \x1b]4;1;rgb:cc/24/1d\x1b\\\
+                      \x1b]11;rgb:fbfb/f1f1/c7c7\x07\
+                      \x1b[?62;4;22c";
+        let colors = TerminalColors::parse(reply, 3);
+        assert_eq!(colors.palette, vec![Some((40, 40, 40)), Some((204, 36, 29)), None]);
+        assert_eq!(colors.foreground, None);
+        assert_eq!(colors.background, Some((251, 241, 199)));
+        assert_eq!(colors.palette_16()[2], COLORS[2]);
+
+        assert_eq!(da1(reply), Some(vec![62, 4, 22]));
+        assert_eq!(da1(b"\x1b]4;0;rgb:2828/2828/2828\x07"), None);
+    }
+    #[test]
+    fn test_cell_size() {
+        assert_eq!(parse_cell_size(b"\x1b[6;17;8t\x1b[4;408;640t\x1b[?62;4c", (80, 24)), Some((8, 17)));
+        assert_eq!(parse_cell_size(b"\x1b[4;408;640t\x1b[?62;4c", (80, 24)), Some((8, 17)));
+        assert_eq!(parse_cell_size(b"\x1b[4;0;0t", (80, 24)), None);
+        assert_eq!(parse_cell_size(b"\x1b[?62;4c", (80, 24)), None);
+
+        assert_eq!(per_cell((640, 408), (80, 24)), Some((8, 17)));
+        assert_eq!(per_cell((40, 408), (80, 24)), None);
+        assert_eq!(per_cell((640, 408), (0, 0)), None);
+    }
+}
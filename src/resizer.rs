@@ -1,5 +1,7 @@
 //! Functions to help with resizing math, like keeping aspect ratio
 
+use std::sync::Arc;
+
 /// Calculate the maximum width/height that fits within new_width/new_height,
 /// but still keeps the aspect ratio.
 pub fn keep_aspect_ratio(old_width: u32, old_height: u32, mut new_width: u32, mut new_height: u32) -> (u32, u32) {
@@ -13,21 +15,63 @@ pub fn keep_aspect_ratio(old_width: u32, old_height: u32, mut new_width: u32, mu
         // height is too big
         new_height = (old_height as f64 * (new_width as f64 / old_width as f64)) as u32;
     }
-    (new_width, new_height)
+    (new_width.max(1), new_height.max(1))
 }
-#[deprecated(since = "2.0.2", note = "use apply_font_ratio, which supports a max width")]
-pub fn with_font_ratio(width: u32, ratio: u8) -> u32 {
-    (width as f64 * (ratio as f64 / 100.0 + 1.0)) as u32
+/// Return how wide a pixel looks compared to how tall it looks, on a terminal with
+/// `cell_size` big cells where the converter puts `pixels_per_cell` pixels in each.
+/// The cell size can be in any unit, like screen pixels from `query::cell_size`,
+/// or just `(1.0, 2.0)` for cells that are twice as tall as they're wide.
+pub fn cell_aspect(cell_size: (f64, f64), pixels_per_cell: (u32, u32)) -> f64 {
+    let width = cell_size.0 / pixels_per_cell.0 as f64;
+    let height = cell_size.1 / pixels_per_cell.1 as f64;
+    width / height
 }
-/// Expand width to better match with the font ratio, unless it becomes more than max_width,
-/// which in case it instead shrinks the height.
-pub fn apply_pixel_ratio(ratio: u8, width: u32, height: u32, max_width: u32) -> (u32, u32) {
-    let ratio = 1.0 + ratio as f64 / 100.0;
-    let new_width = (width as f64 * ratio) as u32;
-    if new_width <= max_width {
-        (new_width, height)
+/// Return the biggest part of an old_width x old_height image that has the aspect ratio `ratio`,
+/// placed according to `gravity`. Returns x, y, width and height.
+fn crop_to_ratio(old_width: u32, old_height: u32, ratio: f64, gravity: Gravity) -> (u32, u32, u32, u32) {
+    let (width, height) = if old_width as f64 / old_height as f64 > ratio {
+        // too wide
+        ((old_height as f64 * ratio).round() as u32, old_height)
     } else {
-        (width, (height as f64 * (1.0 / ratio)) as u32)
+        // too tall
+        (old_width, (old_width as f64 / ratio).round() as u32)
+    };
+    let (width, height) = (width.max(1).min(old_width), height.max(1).min(old_height));
+    let (x, y) = gravity.align((old_width, old_height), (width, height));
+    (x, y, width, height)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+/// Which side of an image to keep when it has to be cropped
+pub enum Gravity {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight
+}
+impl Gravity {
+    /// Return the position of something `inner` big inside something `outer` big,
+    /// when it's pushed towards this side
+    pub fn align(self, outer: (u32, u32), inner: (u32, u32)) -> (u32, u32) {
+        let free_x = outer.0.saturating_sub(inner.0);
+        let free_y = outer.1.saturating_sub(inner.1);
+        let x = match self {
+            Gravity::TopLeft | Gravity::Left | Gravity::BottomLeft => 0,
+            Gravity::Top | Gravity::Center | Gravity::Bottom => free_x / 2,
+            Gravity::TopRight | Gravity::Right | Gravity::BottomRight => free_x
+        };
+        let y = match self {
+            Gravity::TopLeft | Gravity::Top | Gravity::TopRight => 0,
+            Gravity::Left | Gravity::Center | Gravity::Right => free_y / 2,
+            Gravity::BottomLeft | Gravity::Bottom | Gravity::BottomRight => free_y
+        };
+        (x, y)
     }
 }
 
@@ -35,25 +79,134 @@ pub fn apply_pixel_ratio(ratio: u8, width: u32, height: u32, max_width: u32) ->
 pub trait Sizer {
     /// Return destination size from old width/height
     fn get_size(&self, old_width: u32, old_height: u32) -> (u32, u32);
+    /// Return the part of the image that should be shown, as x, y, width and height.
+    /// This part is what gets resized to `get_size`. Defaults to the whole image.
+    fn crop(&self, old_width: u32, old_height: u32) -> (u32, u32, u32, u32) {
+        (0, 0, old_width, old_height)
+    }
+}
+impl<S: Sizer + ?Sized> Sizer for Arc<S> {
+    fn get_size(&self, old_width: u32, old_height: u32) -> (u32, u32) {
+        (**self).get_size(old_width, old_height)
+    }
+    fn crop(&self, old_width: u32, old_height: u32) -> (u32, u32, u32, u32) {
+        (**self).crop(old_width, old_height)
+    }
 }
 
-/// An implementation for the Sizer trait which keeps aspect ratio
-/// and optionally applies pixel ratio.
+/// An implementation for the Sizer trait which keeps aspect ratio.
+/// Wrap it in a `CellAspectSizer` for pixels that aren't square.
 #[derive(Clone, Debug)]
 pub struct StandardSizer {
     pub new_width: u32,
-    pub new_height: u32,
-    pub ratio: Option<u8>
+    pub new_height: u32
 }
 impl Sizer for StandardSizer {
     fn get_size(&self, old_width: u32, old_height: u32) -> (u32, u32) {
-        let (mut width, mut height) = keep_aspect_ratio(old_width, old_height, self.new_width, self.new_height);
-        if let Some(ratio) = self.ratio {
-            let (w, h) = apply_pixel_ratio(ratio, width, height, self.new_width);
-            width = w;
-            height = h;
+        keep_aspect_ratio(old_width, old_height, self.new_width, self.new_height)
+    }
+}
+
+/// A Sizer which fills the whole area, keeping aspect ratio by cropping
+/// whatever doesn't fit. The `gravity` decides which part is kept.
+#[derive(Clone, Debug)]
+pub struct CoverSizer {
+    pub new_width: u32,
+    pub new_height: u32,
+    pub gravity: Gravity
+}
+impl Sizer for CoverSizer {
+    fn get_size(&self, _old_width: u32, _old_height: u32) -> (u32, u32) {
+        (self.new_width, self.new_height)
+    }
+    fn crop(&self, old_width: u32, old_height: u32) -> (u32, u32, u32, u32) {
+        let ratio = self.new_width as f64 / self.new_height as f64;
+        crop_to_ratio(old_width, old_height, ratio, self.gravity)
+    }
+}
+
+/// A Sizer which stretches the image to exactly the area, ignoring aspect ratio
+#[derive(Clone, Debug)]
+pub struct StretchSizer {
+    pub new_width: u32,
+    pub new_height: u32
+}
+impl Sizer for StretchSizer {
+    fn get_size(&self, _old_width: u32, _old_height: u32) -> (u32, u32) {
+        (self.new_width, self.new_height)
+    }
+}
+
+/// A Sizer which uses the whole width and keeps aspect ratio,
+/// however tall that makes the image. Meant for scrolling vertically.
+#[derive(Clone, Debug)]
+pub struct FitWidthSizer {
+    pub new_width: u32
+}
+impl Sizer for FitWidthSizer {
+    fn get_size(&self, old_width: u32, old_height: u32) -> (u32, u32) {
+        let height = old_height as f64 * self.new_width as f64 / old_width as f64;
+        (self.new_width, (height.round() as u32).max(1))
+    }
+}
+
+/// A Sizer which uses the whole height and keeps aspect ratio.
+/// Terminals can't scroll sideways, so an image that becomes wider than
+/// `new_width` is cropped, keeping the part `gravity` decides.
+#[derive(Clone, Debug)]
+pub struct FitHeightSizer {
+    pub new_width: u32,
+    pub new_height: u32,
+    pub gravity: Gravity
+}
+impl Sizer for FitHeightSizer {
+    fn get_size(&self, old_width: u32, old_height: u32) -> (u32, u32) {
+        let width = old_width as f64 * self.new_height as f64 / old_height as f64;
+        ((width.round() as u32).max(1).min(self.new_width), self.new_height)
+    }
+    fn crop(&self, old_width: u32, old_height: u32) -> (u32, u32, u32, u32) {
+        let (width, height) = self.get_size(old_width, old_height);
+        if width < self.new_width {
+            return (0, 0, old_width, old_height);
+        }
+        let ratio = width as f64 / height as f64;
+        crop_to_ratio(old_width, old_height, ratio, self.gravity)
+    }
+}
+
+/// A Sizer which corrects for pixels that don't look square once displayed,
+/// like when a text converter puts one pixel in a cell that's twice as tall as it's wide.
+/// The image is given to `sizer` as if it was stretched to match, so any other Sizer
+/// can be used with it.
+#[derive(Clone, Debug)]
+pub struct CellAspectSizer<S: Sizer> {
+    pub sizer: S,
+    /// How wide a pixel looks compared to how tall it looks
+    pub aspect: f64
+}
+impl<S: Sizer> CellAspectSizer<S> {
+    /// Create a sizer for cells that are `cell_size` big, where the converter
+    /// puts `pixels_per_cell` pixels in each. See `cell_aspect`.
+    pub fn new(sizer: S, cell_size: (f64, f64), pixels_per_cell: (u32, u32)) -> Self {
+        Self {
+            sizer,
+            aspect: cell_aspect(cell_size, pixels_per_cell)
         }
-        (width, height)
+    }
+    /// Return the width the image would have, stretched to cancel out the aspect
+    fn stretched_width(&self, old_width: u32) -> u32 {
+        ((old_width as f64 / self.aspect).round() as u32).max(1)
+    }
+}
+impl<S: Sizer> Sizer for CellAspectSizer<S> {
+    fn get_size(&self, old_width: u32, old_height: u32) -> (u32, u32) {
+        self.sizer.get_size(self.stretched_width(old_width), old_height)
+    }
+    fn crop(&self, old_width: u32, old_height: u32) -> (u32, u32, u32, u32) {
+        let (x, y, width, height) = self.sizer.crop(self.stretched_width(old_width), old_height);
+        let unstretch = |x: u32| ((x as f64 * self.aspect).round() as u32).min(old_width);
+        let x = unstretch(x);
+        (x, y, unstretch(width).max(1).min(old_width - x), height)
     }
 }
 
@@ -61,16 +214,114 @@ impl Sizer for StandardSizer {
 mod tests {
     use super::*;
 
+    /// A tiny xorshift generator, so property tests are random but repeatable
+    struct Random(u64);
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn range(&mut self, min: u32, max: u32) -> u32 {
+            min + (self.next() % (max - min + 1) as u64) as u32
+        }
+        fn float(&mut self, min: f64, max: f64) -> f64 {
+            min + (self.next() % 1_000_000) as f64 / 1_000_000.0 * (max - min)
+        }
+    }
+
     #[test]
     fn test_aspect_ratio() {
         assert_eq!(keep_aspect_ratio(2, 1, 4, 5), (4, 2));
         assert_eq!(keep_aspect_ratio(1, 2, 5, 4), (2, 4));
 
         assert_eq!(keep_aspect_ratio(1092, 614, 167, 40), (71, 40));
+        assert_eq!(keep_aspect_ratio(1, 1000, 80, 24), (1, 24));
+    }
+    #[test]
+    fn test_cell_aspect() {
+        assert_eq!(cell_aspect((8.0, 16.0), (1, 1)), 0.5);
+        assert_eq!(cell_aspect((8.0, 16.0), (1, 2)), 1.0);
+        assert_eq!(cell_aspect((10.0, 10.0), (10, 10)), 1.0);
+        assert_eq!(cell_aspect((2.0, 1.0), (1, 1)), 2.0);
+    }
+    #[test]
+    fn test_cell_aspect_sizer() {
+        let standard = StandardSizer { new_width: 80, new_height: 24 };
+
+        // Each pixel of a text converter is a whole 8x16 cell, so images need twice the columns
+        let sizer = CellAspectSizer::new(standard.clone(), (8.0, 16.0), (1, 1));
+        assert_eq!(sizer.aspect, 0.5);
+        assert_eq!(sizer.get_size(100, 100), (48, 24));
+        // With half blocks, pixels are square again
+        let sizer = CellAspectSizer::new(standard, (8.0, 16.0), (1, 2));
+        assert_eq!(sizer.get_size(100, 100), (24, 24));
+
+        let cover = CoverSizer { new_width: 80, new_height: 40, gravity: Gravity::Center };
+        let sizer = CellAspectSizer::new(cover, (8.0, 16.0), (1, 1));
+        assert_eq!(sizer.get_size(100, 100), (80, 40));
+        assert_eq!(sizer.crop(100, 100), (0, 0, 100, 100));
+        assert_eq!(sizer.crop(200, 100), (50, 0, 100, 100));
+
+        let fit_width = CellAspectSizer::new(FitWidthSizer { new_width: 80 }, (1.0, 2.0), (1, 1));
+        assert_eq!(fit_width.get_size(100, 1000), (80, 400));
+    }
+    #[test]
+    fn test_cell_aspect_sizer_properties() {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let (old_width, old_height) = (random.range(1, 4000), random.range(1, 4000));
+            let (new_width, new_height) = (random.range(1, 400), random.range(1, 400));
+            let aspect = random.float(0.2, 5.0);
+            let sizer = CellAspectSizer {
+                sizer: StandardSizer { new_width, new_height },
+                aspect
+            };
+            let (width, height) = sizer.get_size(old_width, old_height);
+            let case = format!("{}x{} in {}x{} at {}", old_width, old_height, new_width, new_height, aspect);
+
+            // Fits, and fills one of the sides
+            assert!(width >= 1 && width <= new_width, "{}", case);
+            assert!(height >= 1 && height <= new_height, "{}", case);
+            assert!(width == new_width || height == new_height, "{}", case);
+
+            // Looks like the original, within rounding
+            let stretched = old_width as f64 / aspect;
+            let scale = (new_width as f64 / stretched).min(new_height as f64 / old_height as f64);
+            let (exact_width, exact_height) = (stretched * scale, old_height as f64 * scale);
+            assert!((width as f64 - exact_width).abs() <= 1.0 + exact_width / stretched, "{}", case);
+            assert!((height as f64 - exact_height).abs() <= 1.0 + exact_height / stretched, "{}", case);
+        }
     }
     #[test]
-    fn test_font_ratio() {
-        assert_eq!(apply_pixel_ratio(50, 5, 3, 10), (7, 3));
-        assert_eq!(apply_pixel_ratio(50, 5, 3, 5),  (5, 2));
+    fn test_gravity() {
+        assert_eq!(Gravity::TopLeft.align((10, 6), (4, 2)), (0, 0));
+        assert_eq!(Gravity::Center.align((10, 6), (4, 2)), (3, 2));
+        assert_eq!(Gravity::Right.align((10, 6), (4, 2)), (6, 2));
+        assert_eq!(Gravity::Bottom.align((10, 6), (4, 2)), (3, 4));
+        assert_eq!(Gravity::BottomRight.align((10, 6), (12, 2)), (0, 4));
+    }
+    #[test]
+    fn test_sizers() {
+        let cover = CoverSizer { new_width: 80, new_height: 40, gravity: Gravity::Center };
+        assert_eq!(cover.get_size(1000, 1000), (80, 40));
+        assert_eq!(cover.crop(1000, 1000), (0, 250, 1000, 500));
+        assert_eq!(cover.crop(400, 100), (100, 0, 200, 100));
+        let cover = CoverSizer { new_width: 40, gravity: Gravity::Top, ..cover };
+        assert_eq!(cover.crop(1000, 1000), (0, 0, 1000, 1000));
+
+        let stretch = StretchSizer { new_width: 80, new_height: 40 };
+        assert_eq!(stretch.get_size(10, 1000), (80, 40));
+        assert_eq!(stretch.crop(10, 1000), (0, 0, 10, 1000));
+
+        let fit_width = FitWidthSizer { new_width: 80 };
+        assert_eq!(fit_width.get_size(100, 1000), (80, 800));
+
+        let fit_height = FitHeightSizer { new_width: 80, new_height: 40, gravity: Gravity::Left };
+        assert_eq!(fit_height.get_size(100, 1000), (4, 40));
+        assert_eq!(fit_height.crop(100, 1000), (0, 0, 100, 1000));
+        assert_eq!(fit_height.get_size(1000, 100), (80, 40));
+        assert_eq!(fit_height.crop(1000, 100), (0, 0, 200, 100));
     }
 }